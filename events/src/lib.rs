@@ -0,0 +1,123 @@
+//! The JSONL event format shared by the kunit test harness, which writes it, and the host tools
+//! (`kunit-host`), which read and validate it: the schema version, the field names and the
+//! spellings of the test results.
+//!
+//! Both sides use these definitions instead of string literals, so a change to the format on one
+//! side is a compile error on the other, rather than a CI failure. The full row shapes are
+//! described by `schema/events.schema.json`.
+
+#![no_std]
+
+/// The version of the JSONL output, reported as `"schema"` in the group header. Version 1
+/// (without a `"schema"` field) had no start events, version 2 had no summary row, and version 3
+/// also wrote start events for tests which were filtered out.
+pub const SCHEMA_VERSION: u64 = 4;
+
+/// The value of [`field::MODE`] in the header of a test listing.
+pub const MODE_LIST: &str = "list";
+
+/// The value of [`field::EVENT`] in a start row.
+pub const EVENT_START: &str = "start";
+
+/// The field names of every row shape.
+pub mod field {
+    // group and list group headers
+    pub const TEST_GROUP: &str = "test_group";
+    pub const TEST_COUNT: &str = "test_count";
+    pub const SCHEMA: &str = "schema";
+    pub const SHUFFLE_SEED: &str = "shuffle_seed";
+    pub const MODE: &str = "mode";
+    pub const CYCLE_FREQUENCY: &str = "cycle_frequency";
+
+    // listings
+    pub const MODULE: &str = "module";
+    pub const NAME: &str = "name";
+    pub const IGNORE: &str = "ignore";
+    pub const SHOULD_PANIC: &str = "should_panic";
+    pub const EXPECTED: &str = "expected";
+    pub const SHOULD_FAULT: &str = "should_fault";
+
+    // start and result rows
+    pub const TEST: &str = "test";
+    pub const EVENT: &str = "event";
+    pub const RESULT: &str = "result";
+    pub const CYCLE_COUNT: &str = "cycle_count";
+    pub const LOCATION: &str = "location";
+    pub const MESSAGE: &str = "message";
+    pub const TIMEOUT_MS: &str = "timeout_ms";
+    pub const EXCEPTION: &str = "exception";
+    pub const ADDRESS: &str = "address";
+    pub const ERROR_CODE: &str = "error_code";
+    pub const REGISTERS: &str = "registers";
+
+    // the summary row, whose totals are nested in a `summary` object
+    pub const SUMMARY: &str = "summary";
+    pub const PASSED: &str = "passed";
+    pub const FAILED: &str = "failed";
+    pub const IGNORED: &str = "ignored";
+    pub const TIMED_OUT: &str = "timed_out";
+    pub const FILTERED: &str = "filtered";
+    pub const TOTAL_CYCLES: &str = "total_cycles";
+    pub const COMPLETE: &str = "complete";
+}
+
+/// The result of a test case, as written in the `result` field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Ignore,
+    Timeout,
+    Filtered,
+}
+
+impl Outcome {
+    /// Every outcome, in the order of the summary row.
+    pub const ALL: [Outcome; 5] = [
+        Outcome::Pass,
+        Outcome::Fail,
+        Outcome::Ignore,
+        Outcome::Timeout,
+        Outcome::Filtered,
+    ];
+
+    /// The spelling of the outcome in the `result` field.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Pass => "pass",
+            Outcome::Fail => "fail",
+            Outcome::Ignore => "ignore",
+            Outcome::Timeout => "timeout",
+            Outcome::Filtered => "filtered",
+        }
+    }
+
+    /// The field of the summary row which counts this outcome.
+    pub const fn summary_field(self) -> &'static str {
+        match self {
+            Outcome::Pass => field::PASSED,
+            Outcome::Fail => field::FAILED,
+            Outcome::Ignore => field::IGNORED,
+            Outcome::Timeout => field::TIMED_OUT,
+            Outcome::Filtered => field::FILTERED,
+        }
+    }
+
+    /// Parses the spelling of an outcome, as written by [`Outcome::as_str`].
+    pub fn parse(s: &str) -> Option<Outcome> {
+        Outcome::ALL
+            .into_iter()
+            .find(|outcome| outcome.as_str() == s)
+    }
+
+    /// Whether the outcome fails the test run.
+    pub const fn is_failure(self) -> bool {
+        matches!(self, Outcome::Fail | Outcome::Timeout)
+    }
+}
+
+impl core::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
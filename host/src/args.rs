@@ -0,0 +1,165 @@
+//! Translation of libtest-style test arguments into kunit harness arguments.
+
+/// The maximum length in bytes of the harness arguments, joined by spaces, which the kernel
+/// accepts (kunit's `MAX_STRING_LENGTH`). The kernel refuses to run with longer arguments.
+pub const MAX_HARNESS_ARGS_LENGTH: usize = 1024;
+
+/// Translates the arguments given after the test image (as passed by `cargo test -- ...`) into
+/// harness arguments:
+/// - a plain argument is a filter (`kunit.filter=...`)
+/// - `--skip <pattern>`, `--exact`, `--list`, `--shuffle` and `--shuffle-seed <seed>` map to the
+///   harness argument of the same name
+/// - `kunit.*` arguments are passed through unchanged
+///
+/// Other libtest flags have no kernel equivalent, and are returned separately so that the caller
+/// can warn about them.
+///
+/// The kernel separates harness arguments by whitespace, so patterns containing whitespace are
+/// rejected, as are arguments longer than [`MAX_HARNESS_ARGS_LENGTH`] in total.
+pub fn harness_args(test_args: &[String]) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut harness_args = Vec::new();
+    let mut unsupported = Vec::new();
+
+    let mut test_args = test_args.iter();
+    while let Some(arg) = test_args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        match flag {
+            "--exact" | "--list" | "--shuffle" => {
+                harness_args.push(format!("kunit.{}", &flag[2..]));
+            }
+            "--skip" | "--shuffle-seed" => {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => test_args
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| format!("missing value for {flag}"))?,
+                };
+                harness_args.push(format!("kunit.{}={value}", &flag[2..]));
+            }
+            // libtest flags which take a value, which must not be mistaken for a filter
+            "--test-threads" | "--format" | "--color" | "--logfile" | "-Z" => {
+                let mut flag = arg.clone();
+                if inline_value.is_none()
+                    && let Some(value) = test_args.next()
+                {
+                    flag = format!("{flag} {value}");
+                }
+                unsupported.push(flag);
+            }
+            _ if arg.starts_with("kunit.") => harness_args.push(arg.clone()),
+            _ if arg.starts_with('-') => unsupported.push(arg.clone()),
+            _ => harness_args.push(format!("kunit.filter={arg}")),
+        }
+    }
+
+    if let Some(arg) = harness_args
+        .iter()
+        .find(|arg| arg.contains(char::is_whitespace))
+    {
+        return Err(format!(
+            "harness argument '{arg}' contains whitespace, which the kernel reads as a separator"
+        ));
+    }
+    let length = harness_args.iter().map(String::len).sum::<usize>() + harness_args.len();
+    if length.saturating_sub(1) > MAX_HARNESS_ARGS_LENGTH {
+        return Err(format!(
+            "the harness arguments are longer than {MAX_HARNESS_ARGS_LENGTH} bytes"
+        ));
+    }
+
+    Ok((harness_args, unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate(args: &[&str]) -> (Vec<String>, Vec<String>) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        harness_args(&args).unwrap()
+    }
+
+    #[test]
+    fn filters_and_flags() {
+        let (harness, unsupported) = translate(&["alloc", "--exact", "--list", "--shuffle"]);
+        assert_eq!(
+            harness,
+            [
+                "kunit.filter=alloc",
+                "kunit.exact",
+                "kunit.list",
+                "kunit.shuffle"
+            ]
+        );
+        assert!(unsupported.is_empty());
+    }
+
+    #[test]
+    fn skip_with_separate_or_inline_value() {
+        let (harness, _) = translate(&["--skip", "x", "--skip=y", "--shuffle-seed", "7"]);
+        assert_eq!(
+            harness,
+            ["kunit.skip=x", "kunit.skip=y", "kunit.shuffle-seed=7"]
+        );
+    }
+
+    #[test]
+    fn missing_value_is_an_error() {
+        assert_eq!(
+            harness_args(&["--skip".to_string()]),
+            Err("missing value for --skip".to_string())
+        );
+    }
+
+    #[test]
+    fn unsupported_flags_keep_their_value() {
+        let (harness, unsupported) = translate(&[
+            "--test-threads",
+            "4",
+            "--format=json",
+            "--nocapture",
+            "alloc",
+        ]);
+        assert_eq!(harness, ["kunit.filter=alloc"]);
+        assert_eq!(
+            unsupported,
+            ["--test-threads 4", "--format=json", "--nocapture"]
+        );
+    }
+
+    #[test]
+    fn rejects_whitespace_in_patterns() {
+        assert_eq!(
+            harness_args(&["alloc grow".to_string()]),
+            Err(
+                "harness argument 'kunit.filter=alloc grow' contains whitespace, which the kernel reads as a separator"
+                    .to_string()
+            )
+        );
+        assert!(harness_args(&["--skip".to_string(), "a\tb".to_string()]).is_err());
+    }
+
+    #[test]
+    fn rejects_arguments_over_the_kernel_limit() {
+        // "kunit.filter=" is 13 bytes
+        let fits = "x".repeat(MAX_HARNESS_ARGS_LENGTH - 13);
+        assert!(harness_args(std::slice::from_ref(&fits)).is_ok());
+        let too_long = "x".repeat(MAX_HARNESS_ARGS_LENGTH - 12);
+        assert_eq!(
+            harness_args(&[too_long]),
+            Err("the harness arguments are longer than 1024 bytes".to_string())
+        );
+    }
+
+    #[test]
+    fn passes_harness_arguments_through() {
+        let (harness, unsupported) = translate(&["kunit.timeout=500", "kunit.exact"]);
+        assert_eq!(harness, ["kunit.timeout=500", "kunit.exact"]);
+        assert!(unsupported.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use kunit_host::validate;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: kunit-validate [--github] <TESTING_DIR>
+
+Validates the test result files collected by k1 (e.g. .k1/x86_64/testing): every
+testing-<group>.jsonl file must be complete and well-formed, and no test may have failed.
+
+Options:
+  --github  report problems as GitHub Actions annotations";
+
+fn main() -> ExitCode {
+    let mut github = false;
+    let mut testing_dir = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--github" => github = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ if arg.starts_with('-') || testing_dir.is_some() => {
+                eprintln!("error: unexpected argument '{arg}'\n\n{USAGE}");
+                return ExitCode::from(2);
+            }
+            _ => testing_dir = Some(PathBuf::from(arg)),
+        }
+    }
+    let Some(testing_dir) = testing_dir else {
+        eprintln!("error: missing testing directory\n\n{USAGE}");
+        return ExitCode::from(2);
+    };
+
+    let validation = validate::validate_dir(&testing_dir);
+    if !validation.is_valid() {
+        for failure in &validation.failures {
+            if github {
+                println!("::error::{failure}");
+            } else {
+                eprintln!("error: {failure}");
+            }
+        }
+        return ExitCode::FAILURE;
+    }
+
+    println!("Validated test results in {}:", testing_dir.display());
+    if let Some(totals) = validation.totals {
+        println!("- passed: {}", totals.passed);
+        println!("- failed: {}", totals.failed);
+        println!("- ignored: {}", totals.ignored);
+    }
+    if !validation.ignored_tests.is_empty() {
+        let ignored_tests: std::collections::BTreeSet<_> =
+            validation.ignored_tests.iter().collect();
+        let ignored_tests: Vec<_> = ignored_tests.into_iter().map(String::as_str).collect();
+        let message = format!("Ignored tests: {}", ignored_tests.join(", "));
+        if github {
+            println!("::warning::{message}");
+        } else {
+            eprintln!("warning: {message}");
+        }
+    }
+    for group in &validation.groups {
+        println!("- {group}");
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,310 @@
+//! Comparison of two test runs, to find the tests which regressed after a kernel change.
+//!
+//! Every test is classified by the change of its result between the old and the new run, and
+//! tests which passed in both runs by the change of their cycle count. A test counts as slower (or
+//! faster) only if its cycle count changed by more than both thresholds, the relative one and the
+//! absolute one, so that short tests with noisy timings are not reported.
+//!
+//! The runs are read from JSONL files, which have the result and cycle count of every test. A
+//! `results.json` only lists the failed and ignored tests; a run read from one (without JSONL
+//! files) is not exhaustive, so tests missing from it are not reported as added or disappeared.
+
+use crate::event::{Outcome, TestSuite};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, Write};
+
+/// The exit code of `kunit diff --fail-on-regression` if a test regressed, the one libtest uses for
+/// test failures, distinct from the exit code 1 of invalid arguments or unreadable runs.
+pub const EXIT_CODE_REGRESSION: u8 = 101;
+
+/// The result of a test in one run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Outcome(Outcome),
+    /// The test started but never reported a result.
+    Crashed,
+}
+
+impl Status {
+    /// Whether the status fails the test run.
+    pub fn is_failure(self) -> bool {
+        match self {
+            Status::Outcome(outcome) => outcome.is_failure(),
+            Status::Crashed => true,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Outcome(outcome) => outcome.fmt(f),
+            Status::Crashed => f.write_str("crash"),
+        }
+    }
+}
+
+/// The result and cycle count of a test in one run. The cycle count is not known for tests read
+/// from a `results.json`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TestRun {
+    pub status: Status,
+    pub cycle_count: Option<u64>,
+}
+
+/// The results of a test run, by qualified test name.
+#[derive(Clone, Debug, Default)]
+pub struct RunResults {
+    pub tests: BTreeMap<String, TestRun>,
+    /// Whether every test of the run is known, which is not the case if it was (partly) read from
+    /// a `results.json` rather than JSONL files.
+    pub exhaustive: bool,
+    /// Whether any tests were read from a `results.json`.
+    has_results_json: bool,
+}
+
+impl RunResults {
+    /// Adds the results of a test suite read from a JSONL file.
+    pub fn add_suite(&mut self, suite: &TestSuite) {
+        for result in &suite.results {
+            let run = TestRun {
+                status: Status::Outcome(result.result),
+                cycle_count: Some(result.cycle_count),
+            };
+            self.tests.insert(result.test.clone(), run);
+        }
+        if let Some(test) = &suite.unfinished {
+            let run = TestRun {
+                status: Status::Crashed,
+                cycle_count: None,
+            };
+            self.tests.insert(test.clone(), run);
+        }
+        self.exhaustive = !self.has_results_json;
+    }
+
+    /// Adds the failed and ignored tests listed in a `results.json`. Tests which are already known
+    /// from a JSONL file keep their result.
+    pub fn add_results_json(&mut self, results: &Value) -> Result<(), String> {
+        for (key, outcome) in [
+            ("failed_tests", Outcome::Fail),
+            ("ignored_tests", Outcome::Ignore),
+        ] {
+            let tests = results
+                .get(key)
+                .and_then(Value::as_array)
+                .ok_or_else(|| format!("{key} must be an array of strings"))?;
+            for test in tests {
+                let test = test
+                    .as_str()
+                    .ok_or_else(|| format!("{key} must be an array of strings"))?;
+                self.tests.entry(test.to_string()).or_insert(TestRun {
+                    status: Status::Outcome(outcome),
+                    cycle_count: None,
+                });
+            }
+        }
+        self.has_results_json = true;
+        self.exhaustive = false;
+        Ok(())
+    }
+}
+
+/// The thresholds above which a change of cycle count is reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    /// The relative change, in percent of the old cycle count.
+    pub percent: f64,
+    /// The absolute change, in cycles.
+    pub cycles: u64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            percent: 20.0,
+            cycles: 10_000,
+        }
+    }
+}
+
+/// How a test changed between the old and the new run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Change {
+    /// The test failed in the new run, but not in the old one.
+    NewlyFailing,
+    /// The test passed in both runs, and became slower by more than the thresholds.
+    Slower,
+    /// The test is in the old run, but not in the new one.
+    Disappeared,
+    /// The test is in the new run, but not in the old one.
+    Added,
+    /// The test failed in the old run, but not in the new one.
+    NewlyPassing,
+    /// The test failed in both runs.
+    StillFailing,
+    /// The result changed in another way, e.g. a passing test is now ignored.
+    StatusChanged,
+    /// The test passed in both runs, and became faster by more than the thresholds.
+    Faster,
+    /// The result and cycle count did not change (beyond the thresholds).
+    Unchanged,
+}
+
+impl Change {
+    /// Every change, in the order they are reported.
+    pub const ALL: [Change; 9] = [
+        Change::NewlyFailing,
+        Change::Slower,
+        Change::Disappeared,
+        Change::Added,
+        Change::NewlyPassing,
+        Change::StillFailing,
+        Change::StatusChanged,
+        Change::Faster,
+        Change::Unchanged,
+    ];
+
+    /// A short description of the change.
+    pub fn description(self) -> &'static str {
+        match self {
+            Change::NewlyFailing => "newly failing",
+            Change::Slower => "slower",
+            Change::Disappeared => "disappeared",
+            Change::Added => "added",
+            Change::NewlyPassing => "newly passing",
+            Change::StillFailing => "still failing",
+            Change::StatusChanged => "changed",
+            Change::Faster => "faster",
+            Change::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// The comparison of one test between the two runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestDiff {
+    pub test: String,
+    pub change: Change,
+    pub old: Option<TestRun>,
+    pub new: Option<TestRun>,
+}
+
+impl TestDiff {
+    /// Whether the change is a regression: the test fails in the new run but did not fail in the
+    /// old one (including a new test which fails), or it became slower.
+    pub fn is_regression(&self) -> bool {
+        match self.change {
+            Change::NewlyFailing | Change::Slower => true,
+            Change::Added => self.new.is_some_and(|new| new.status.is_failure()),
+            _ => false,
+        }
+    }
+
+    /// The change of cycle count in percent of the old cycle count, if both are known.
+    pub fn cycle_change_percent(&self) -> Option<f64> {
+        let old = self.old?.cycle_count?;
+        let new = self.new?.cycle_count?;
+        (old != 0).then(|| (new as f64 - old as f64) * 100.0 / old as f64)
+    }
+}
+
+/// Compares every test of the two runs. The tests are in order of their name.
+pub fn diff(old: &RunResults, new: &RunResults, thresholds: Thresholds) -> Vec<TestDiff> {
+    let tests: BTreeSet<&String> = old.tests.keys().chain(new.tests.keys()).collect();
+    tests
+        .into_iter()
+        .filter_map(|test| {
+            let old_run = old.tests.get(test).copied();
+            let new_run = new.tests.get(test).copied();
+            let change = match (old_run, new_run) {
+                (Some(old_run), Some(new_run)) => compare(old_run, new_run, thresholds),
+                // without the full list of tests, a missing test may just not be listed
+                (Some(_), None) if new.exhaustive => Change::Disappeared,
+                (None, Some(_)) if old.exhaustive => Change::Added,
+                _ => return None,
+            };
+            Some(TestDiff {
+                test: test.clone(),
+                change,
+                old: old_run,
+                new: new_run,
+            })
+        })
+        .collect()
+}
+
+/// Classifies a test which is in both runs.
+fn compare(old: TestRun, new: TestRun, thresholds: Thresholds) -> Change {
+    match (old.status.is_failure(), new.status.is_failure()) {
+        (false, true) => return Change::NewlyFailing,
+        (true, false) => return Change::NewlyPassing,
+        (true, true) => return Change::StillFailing,
+        (false, false) if old.status != new.status => return Change::StatusChanged,
+        (false, false) => {}
+    }
+
+    let (Some(old_cycles), Some(new_cycles)) = (old.cycle_count, new.cycle_count) else {
+        return Change::Unchanged;
+    };
+    if old.status != Status::Outcome(Outcome::Pass) {
+        return Change::Unchanged;
+    }
+    let delta = new_cycles.abs_diff(old_cycles);
+    let percent = delta as f64 * 100.0 / old_cycles.max(1) as f64;
+    if delta <= thresholds.cycles || percent <= thresholds.percent {
+        Change::Unchanged
+    } else if new_cycles > old_cycles {
+        Change::Slower
+    } else {
+        Change::Faster
+    }
+}
+
+/// Writes the changed tests, grouped by change, followed by the number of tests with each change.
+/// Unchanged tests are only counted.
+pub fn write_report<W: Write>(out: &mut W, diffs: &[TestDiff]) -> io::Result<()> {
+    let width = diffs.iter().map(|diff| diff.test.len()).max().unwrap_or(0);
+    for change in Change::ALL {
+        let tests: Vec<&TestDiff> = diffs.iter().filter(|diff| diff.change == change).collect();
+        if tests.is_empty() || change == Change::Unchanged {
+            continue;
+        }
+        writeln!(out, "{} ({}):", change.description(), tests.len())?;
+        for diff in tests {
+            writeln!(out, "    {:width$}  {}", diff.test, describe(diff))?;
+        }
+        writeln!(out)?;
+    }
+
+    let counts: Vec<String> = Change::ALL
+        .into_iter()
+        .map(|change| {
+            let count = diffs.iter().filter(|diff| diff.change == change).count();
+            format!("{count} {}", change.description())
+        })
+        .collect();
+    let regressions = diffs.iter().filter(|diff| diff.is_regression()).count();
+    let plural = if regressions == 1 { "" } else { "s" };
+    writeln!(
+        out,
+        "diff result: {regressions} regression{plural}. {}",
+        counts.join("; ")
+    )
+}
+
+/// Describes the change of one test, e.g. `pass -> fail` or `1200 -> 1800 cycles (+50.0%)`.
+fn describe(diff: &TestDiff) -> String {
+    let cycles = |run: Option<TestRun>| run.and_then(|run| run.cycle_count);
+    if let (Change::Slower | Change::Faster, Some(old), Some(new)) =
+        (diff.change, cycles(diff.old), cycles(diff.new))
+    {
+        let percent = diff.cycle_change_percent().unwrap_or(0.0);
+        return format!("{old} -> {new} cycles ({percent:+.1}%)");
+    }
+
+    let status = |run: Option<TestRun>| run.map_or("-".to_string(), |run| run.status.to_string());
+    format!("{} -> {}", status(diff.old), status(diff.new))
+}
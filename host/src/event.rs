@@ -0,0 +1,654 @@
+//! Decoding of the JSONL events written by the kernel, as defined by `test::output` in the kunit
+//! crate (and described by `schema/events.schema.json`).
+//!
+//! Field names and result spellings come from `kunit_events`, which the kernel writer uses as
+//! well. Unknown fields are ignored, as the schema requires, so that output from a newer kunit with
+//! additional fields can still be read.
+
+use kunit_events::field;
+use serde_json::{Map, Value};
+use std::fmt;
+
+pub use kunit_events::Outcome;
+
+/// A single row of JSONL output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The first row of a test run.
+    Group(Group),
+    /// The first row of a test listing (`kunit.list`).
+    ListGroup(Group),
+    /// A test case described without running it.
+    Listing(Listing),
+    /// A test case is about to start.
+    Start { test: String },
+    /// The result of one test case.
+    Result(TestResult),
+    /// The last row of a test run.
+    Summary(Summary),
+}
+
+/// The header of a test run or listing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub test_group: String,
+    pub test_count: u64,
+    /// The schema version; output without a `"schema"` field is version 1.
+    pub schema: u64,
+    pub shuffle_seed: Option<u64>,
+    /// The frequency of the cycle counter in Hz, if the kernel could determine it.
+    pub cycle_frequency: Option<u64>,
+}
+
+/// A test case from a listing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listing {
+    pub test: String,
+    pub module: String,
+    pub name: String,
+    pub ignore: bool,
+    pub should_panic: bool,
+    pub expected: Option<String>,
+    pub should_fault: Option<String>,
+}
+
+/// A test result row. Fields beyond `test`, `result` and `cycle_count` depend on the outcome.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    pub test: String,
+    pub result: Outcome,
+    pub cycle_count: u64,
+    /// The failure location (for `fail`), or the faulting instruction for a CPU exception.
+    pub location: Option<String>,
+    pub message: Option<String>,
+    pub timeout_ms: Option<u64>,
+    /// The type of CPU exception which failed the test, if any.
+    pub exception: Option<String>,
+    pub address: Option<String>,
+    pub error_code: Option<String>,
+    /// The register dump of a CPU exception, in the order written by the kernel.
+    pub registers: Vec<(String, String)>,
+}
+
+/// The totals of a test run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub passed: u64,
+    pub failed: u64,
+    pub ignored: u64,
+    pub timed_out: u64,
+    pub filtered: u64,
+    pub total_cycles: u64,
+    pub complete: bool,
+}
+
+impl Summary {
+    /// The number of test results counted by the summary.
+    pub fn total(&self) -> u64 {
+        Outcome::ALL
+            .into_iter()
+            .map(|outcome| self.count(outcome))
+            .sum()
+    }
+
+    /// The count of the given outcome.
+    pub fn count(&self, outcome: Outcome) -> u64 {
+        match outcome {
+            Outcome::Pass => self.passed,
+            Outcome::Fail => self.failed,
+            Outcome::Ignore => self.ignored,
+            Outcome::Timeout => self.timed_out,
+            Outcome::Filtered => self.filtered,
+        }
+    }
+
+    /// Adds one result to the totals.
+    pub fn record(&mut self, result: &TestResult) {
+        let count = match result.result {
+            Outcome::Pass => &mut self.passed,
+            Outcome::Fail => &mut self.failed,
+            Outcome::Ignore => &mut self.ignored,
+            Outcome::Timeout => &mut self.timed_out,
+            Outcome::Filtered => &mut self.filtered,
+        };
+        *count += 1;
+        self.total_cycles = self.total_cycles.saturating_add(result.cycle_count);
+    }
+}
+
+impl TestResult {
+    /// The details of a failed test, as printed by libtest: the timeout, or the location and
+    /// message of the panic (or of the CPU exception, together with its register dump). Each line
+    /// ends with a newline.
+    pub fn failure_details(&self) -> String {
+        let mut details = String::new();
+        if let Some(timeout_ms) = self.timeout_ms {
+            details.push_str(&format!("test exceeded its timeout of {timeout_ms} ms\n"));
+        }
+        if let Some(location) = &self.location {
+            let verb = if self.exception.is_some() {
+                "raised a CPU exception"
+            } else {
+                "panicked"
+            };
+            details.push_str(&format!("{verb} at {location}:\n"));
+        }
+        if let Some(message) = &self.message {
+            details.push_str(message);
+            details.push('\n');
+        }
+        for (name, value) in &self.registers {
+            details.push_str(&format!("{name:>8}: {value}\n"));
+        }
+        details
+    }
+}
+
+/// The results of one test group, read from its JSONL file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestSuite {
+    pub group: Group,
+    /// The test result rows, in the order they were written.
+    pub results: Vec<TestResult>,
+    /// The test which started but never reported a result, if the run was cut off in a test.
+    pub unfinished: Option<String>,
+    /// The error decoding the last line, if the file ends in a malformed (cut off) row.
+    pub truncated: Option<String>,
+    /// Whether the file has a summary row (always false before schema version 3).
+    pub complete: bool,
+}
+
+impl TestSuite {
+    /// Reads the results of a test run from the contents of a JSONL file.
+    ///
+    /// A malformed last line is what a run cut off while writing a row (e.g. when QEMU is killed)
+    /// leaves behind, so it is recorded in `truncated` rather than rejecting the rows before it.
+    /// A malformed line anywhere else is an error.
+    pub fn read(contents: &str) -> Result<TestSuite, String> {
+        let lines: Vec<(usize, &str)> = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| (index + 1, line))
+            .collect();
+        let parse = |(index, line): (usize, &str)| {
+            parse_line(line).map_err(|error| format!("line {index}: {error}"))
+        };
+
+        let group = match lines.first().copied().map(parse).transpose()? {
+            Some(Event::Group(group)) => group,
+            Some(Event::ListGroup(_)) => {
+                return Err("file is a test listing, not a test run".into());
+            }
+            _ => return Err("missing test group header".into()),
+        };
+        let mut suite = TestSuite {
+            group,
+            results: Vec::new(),
+            unfinished: None,
+            truncated: None,
+            complete: false,
+        };
+        for (position, &(index, line)) in lines.iter().enumerate().skip(1) {
+            let event = match parse((index, line)) {
+                Ok(event) => event,
+                Err(error) if position == lines.len() - 1 => {
+                    suite.truncated = Some(error);
+                    break;
+                }
+                Err(error) => return Err(error),
+            };
+            match event {
+                Event::Start { test } => suite.unfinished = Some(test),
+                Event::Result(result) => {
+                    suite.unfinished = None;
+                    suite.results.push(result);
+                }
+                Event::Summary(summary) => suite.complete = summary.complete,
+                _ => return Err(format!("line {index}: unexpected row in a test run")),
+            }
+        }
+        Ok(suite)
+    }
+
+    /// The reason the run is incomplete, if it is.
+    pub fn incomplete(&self) -> Option<String> {
+        if let Some(test) = &self.unfinished {
+            Some(format!(
+                "test {test} started but never completed (crash or hang)"
+            ))
+        } else if let Some(error) = &self.truncated {
+            Some(format!(
+                "the test run was cut off in the last row ({error})"
+            ))
+        } else if self.group.schema >= 3 && !self.complete {
+            Some("the test run was cut off before the summary".into())
+        } else {
+            None
+        }
+    }
+}
+
+/// An error decoding a line of output.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The line is not valid JSON.
+    Json(serde_json::Error),
+    /// The line is a JSON value, but not a known row shape.
+    UnknownRow,
+    /// A required field is missing.
+    MissingField(&'static str),
+    /// A field has the wrong type.
+    InvalidField(&'static str),
+    /// The `result` field is not one of the result spellings.
+    UnknownResult(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Json(error) => write!(f, "invalid JSON ({error})"),
+            ParseError::UnknownRow => f.write_str("unknown row shape"),
+            ParseError::MissingField(name) => write!(f, "missing field '{name}'"),
+            ParseError::InvalidField(name) => write!(f, "invalid field '{name}'"),
+            ParseError::UnknownResult(result) => write!(f, "unknown result '{result}'"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Json(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(error: serde_json::Error) -> Self {
+        ParseError::Json(error)
+    }
+}
+
+/// Decodes one line of JSONL output.
+pub fn parse_line(line: &str) -> Result<Event, ParseError> {
+    match serde_json::from_str(line)? {
+        Value::Object(row) => parse_row(&row),
+        _ => Err(ParseError::UnknownRow),
+    }
+}
+
+/// Decodes one row of JSONL output. The row shape is recognized by its distinguishing field, in
+/// the same way as the schema's `oneOf`.
+pub fn parse_row(row: &Map<String, Value>) -> Result<Event, ParseError> {
+    let row = Row(row);
+
+    if let Some(summary) = row.0.get(field::SUMMARY) {
+        let summary = summary
+            .as_object()
+            .ok_or(ParseError::InvalidField(field::SUMMARY))?;
+        let summary = Row(summary);
+        return Ok(Event::Summary(Summary {
+            passed: summary.u64(field::PASSED)?,
+            failed: summary.u64(field::FAILED)?,
+            ignored: summary.u64(field::IGNORED)?,
+            timed_out: summary.u64(field::TIMED_OUT)?,
+            filtered: summary.u64(field::FILTERED)?,
+            total_cycles: summary.u64(field::TOTAL_CYCLES)?,
+            complete: summary.bool(field::COMPLETE)?,
+        }));
+    }
+
+    if row.0.contains_key(field::TEST_GROUP) {
+        let group = Group {
+            test_group: row.string(field::TEST_GROUP)?,
+            test_count: row.u64(field::TEST_COUNT)?,
+            schema: row.optional_u64(field::SCHEMA)?.unwrap_or(1),
+            shuffle_seed: row.optional_u64(field::SHUFFLE_SEED)?,
+            cycle_frequency: row.optional_u64(field::CYCLE_FREQUENCY)?,
+        };
+        let mode = row.optional_string(field::MODE)?;
+        return Ok(match mode.as_deref() {
+            Some(kunit_events::MODE_LIST) => Event::ListGroup(group),
+            _ => Event::Group(group),
+        });
+    }
+
+    if row.optional_string(field::EVENT)?.as_deref() == Some(kunit_events::EVENT_START) {
+        return Ok(Event::Start {
+            test: row.string(field::TEST)?,
+        });
+    }
+
+    if row.0.contains_key(field::RESULT) {
+        let result = row.string(field::RESULT)?;
+        return Ok(Event::Result(TestResult {
+            test: row.string(field::TEST)?,
+            result: Outcome::parse(&result).ok_or(ParseError::UnknownResult(result))?,
+            cycle_count: row.u64(field::CYCLE_COUNT)?,
+            location: row.optional_string(field::LOCATION)?,
+            message: row.optional_string(field::MESSAGE)?,
+            timeout_ms: row.optional_u64(field::TIMEOUT_MS)?,
+            exception: row.optional_string(field::EXCEPTION)?,
+            address: row.optional_string(field::ADDRESS)?,
+            error_code: row.optional_string(field::ERROR_CODE)?,
+            registers: row.registers()?,
+        }));
+    }
+
+    if row.0.contains_key(field::SHOULD_PANIC) {
+        return Ok(Event::Listing(Listing {
+            test: row.string(field::TEST)?,
+            module: row.string(field::MODULE)?,
+            name: row.string(field::NAME)?,
+            ignore: row.bool(field::IGNORE)?,
+            should_panic: row.bool(field::SHOULD_PANIC)?,
+            expected: row.optional_string(field::EXPECTED)?,
+            should_fault: row.optional_string(field::SHOULD_FAULT)?,
+        }));
+    }
+
+    Err(ParseError::UnknownRow)
+}
+
+/// Typed access to the fields of a row.
+struct Row<'a>(&'a Map<String, Value>);
+
+impl Row<'_> {
+    fn optional<'v, T>(
+        &'v self,
+        name: &'static str,
+        convert: impl FnOnce(&'v Value) -> Option<T>,
+    ) -> Result<Option<T>, ParseError> {
+        match self.0.get(name) {
+            None => Ok(None),
+            Some(value) => convert(value)
+                .map(Some)
+                .ok_or(ParseError::InvalidField(name)),
+        }
+    }
+
+    fn optional_string(&self, name: &'static str) -> Result<Option<String>, ParseError> {
+        self.optional(name, |value| value.as_str().map(str::to_string))
+    }
+
+    fn optional_u64(&self, name: &'static str) -> Result<Option<u64>, ParseError> {
+        self.optional(name, Value::as_u64)
+    }
+
+    fn string(&self, name: &'static str) -> Result<String, ParseError> {
+        self.optional_string(name)?
+            .ok_or(ParseError::MissingField(name))
+    }
+
+    fn u64(&self, name: &'static str) -> Result<u64, ParseError> {
+        self.optional_u64(name)?
+            .ok_or(ParseError::MissingField(name))
+    }
+
+    fn bool(&self, name: &'static str) -> Result<bool, ParseError> {
+        self.optional(name, Value::as_bool)?
+            .ok_or(ParseError::MissingField(name))
+    }
+
+    /// The register dump, in the order written by the kernel (kept by serde_json's
+    /// `preserve_order` feature).
+    fn registers(&self) -> Result<Vec<(String, String)>, ParseError> {
+        let Some(registers) = self.optional(field::REGISTERS, Value::as_object)? else {
+            return Ok(Vec::new());
+        };
+        registers
+            .iter()
+            .map(|(name, value)| match value.as_str() {
+                Some(value) => Ok((name.clone(), value.to_string())),
+                None => Err(ParseError::InvalidField(field::REGISTERS)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_group_header() {
+        let event = parse_line(
+            r#"{"test_group":"alloc","test_count":3,"schema":3,"shuffle_seed":42,"cycle_frequency":1000000}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event,
+            Event::Group(Group {
+                test_group: "alloc".into(),
+                test_count: 3,
+                schema: 3,
+                shuffle_seed: Some(42),
+                cycle_frequency: Some(1_000_000),
+            })
+        );
+    }
+
+    #[test]
+    fn group_header_without_schema_is_version_1() {
+        let Event::Group(group) = parse_line(r#"{"test_group":"alloc","test_count":1}"#).unwrap()
+        else {
+            panic!("not a group header");
+        };
+        assert_eq!(group.schema, 1);
+        assert_eq!(group.shuffle_seed, None);
+        assert_eq!(group.cycle_frequency, None);
+    }
+
+    #[test]
+    fn parses_list_header_and_listing() {
+        let event = parse_line(r#"{"test_group":"alloc","test_count":1,"schema":3,"mode":"list"}"#)
+            .unwrap();
+        assert!(matches!(event, Event::ListGroup(group) if group.test_group == "alloc"));
+
+        let event = parse_line(
+            r#"{"test":"alloc::oom","module":"alloc","name":"oom","ignore":false,"should_panic":true,"expected":"out of memory"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event,
+            Event::Listing(Listing {
+                test: "alloc::oom".into(),
+                module: "alloc".into(),
+                name: "oom".into(),
+                ignore: false,
+                should_panic: true,
+                expected: Some("out of memory".into()),
+                should_fault: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_start_row() {
+        let event = parse_line(r#"{"event":"start","test":"alloc::grow"}"#).unwrap();
+        assert_eq!(
+            event,
+            Event::Start {
+                test: "alloc::grow".into()
+            }
+        );
+    }
+
+    #[test]
+    fn parses_failed_result() {
+        let Event::Result(result) = parse_line(
+            r#"{"test":"alloc::grow","result":"fail","cycle_count":120,"location":"src/alloc.rs:10:5","message":"assertion failed"}"#,
+        )
+        .unwrap() else {
+            panic!("not a result row");
+        };
+        assert_eq!(result.result, Outcome::Fail);
+        assert_eq!(result.cycle_count, 120);
+        assert_eq!(result.location.as_deref(), Some("src/alloc.rs:10:5"));
+        assert_eq!(
+            result.failure_details(),
+            "panicked at src/alloc.rs:10:5:\nassertion failed\n"
+        );
+    }
+
+    #[test]
+    fn parses_timeout_result() {
+        let Event::Result(result) =
+            parse_line(r#"{"test":"t","result":"timeout","cycle_count":9,"timeout_ms":500}"#)
+                .unwrap()
+        else {
+            panic!("not a result row");
+        };
+        assert_eq!(result.result, Outcome::Timeout);
+        assert_eq!(result.timeout_ms, Some(500));
+        assert_eq!(
+            result.failure_details(),
+            "test exceeded its timeout of 500 ms\n"
+        );
+    }
+
+    #[test]
+    fn parses_exception_result_in_register_order() {
+        let Event::Result(result) = parse_line(
+            r#"{"test":"t","result":"fail","cycle_count":1,"location":"0xffff8000","exception":"page fault","address":"0x0","error_code":"0x2","registers":{"rip":"0x1","rsp":"0x2","rax":"0x3"}}"#,
+        )
+        .unwrap() else {
+            panic!("not a result row");
+        };
+        assert_eq!(result.exception.as_deref(), Some("page fault"));
+        assert_eq!(result.address.as_deref(), Some("0x0"));
+        assert_eq!(result.error_code.as_deref(), Some("0x2"));
+        let names: Vec<&str> = result
+            .registers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["rip", "rsp", "rax"]);
+        assert!(
+            result
+                .failure_details()
+                .starts_with("raised a CPU exception at 0xffff8000:\n")
+        );
+    }
+
+    #[test]
+    fn parses_summary() {
+        let event = parse_line(
+            r#"{"summary":{"passed":2,"failed":1,"ignored":0,"timed_out":0,"filtered":4,"total_cycles":300,"complete":true}}"#,
+        )
+        .unwrap();
+        let Event::Summary(summary) = event else {
+            panic!("not a summary row");
+        };
+        assert_eq!(summary.total(), 7);
+        assert!(summary.complete);
+    }
+
+    #[test]
+    fn ignores_unknown_fields() {
+        let event =
+            parse_line(r#"{"test":"t","result":"pass","cycle_count":1,"added_later":[1,2]}"#)
+                .unwrap();
+        assert!(matches!(event, Event::Result(result) if result.result == Outcome::Pass));
+    }
+
+    #[test]
+    fn rejects_malformed_rows() {
+        assert!(matches!(parse_line("{\"test\":"), Err(ParseError::Json(_))));
+        assert!(matches!(parse_line("[1]"), Err(ParseError::UnknownRow)));
+        assert!(matches!(
+            parse_line(r#"{"something":"else"}"#),
+            Err(ParseError::UnknownRow)
+        ));
+        assert!(matches!(
+            parse_line(r#"{"test":"t","result":"pass"}"#),
+            Err(ParseError::MissingField("cycle_count"))
+        ));
+        assert!(matches!(
+            parse_line(r#"{"test":"t","result":"pass","cycle_count":"1"}"#),
+            Err(ParseError::InvalidField("cycle_count"))
+        ));
+        assert!(matches!(
+            parse_line(r#"{"test":"t","result":"skipped","cycle_count":1}"#),
+            Err(ParseError::UnknownResult(result)) if result == "skipped"
+        ));
+    }
+
+    const RUN: &str = r#"{"test_group":"alloc","test_count":2,"schema":3}
+{"event":"start","test":"alloc::a"}
+{"test":"alloc::a","result":"pass","cycle_count":10}
+{"event":"start","test":"alloc::b"}
+"#;
+
+    #[test]
+    fn reads_test_suite() {
+        let contents = format!(
+            "{RUN}{}\n{}\n",
+            r#"{"test":"alloc::b","result":"fail","cycle_count":20}"#,
+            r#"{"summary":{"passed":1,"failed":1,"ignored":0,"timed_out":0,"filtered":0,"total_cycles":30,"complete":true}}"#
+        );
+        let suite = TestSuite::read(&contents).unwrap();
+        assert_eq!(suite.group.test_group, "alloc");
+        assert_eq!(suite.results.len(), 2);
+        assert_eq!(suite.unfinished, None);
+        assert!(suite.complete);
+        assert_eq!(suite.incomplete(), None);
+    }
+
+    #[test]
+    fn reads_crashed_test_suite() {
+        let suite = TestSuite::read(RUN).unwrap();
+        assert_eq!(suite.results.len(), 1);
+        assert_eq!(suite.unfinished.as_deref(), Some("alloc::b"));
+        assert_eq!(
+            suite.incomplete().as_deref(),
+            Some("test alloc::b started but never completed (crash or hang)")
+        );
+    }
+
+    #[test]
+    fn keeps_rows_before_a_cut_off_last_line() {
+        let contents = r#"{"test_group":"alloc","test_count":2,"schema":3}
+{"test":"alloc::a","result":"pass","cycle_count":10}
+{"test":"alloc::b","resu"#;
+        let suite = TestSuite::read(contents).unwrap();
+        assert_eq!(suite.results.len(), 1);
+        assert!(
+            suite
+                .truncated
+                .as_deref()
+                .unwrap()
+                .starts_with("line 3: invalid JSON")
+        );
+        assert!(
+            suite
+                .incomplete()
+                .unwrap()
+                .starts_with("the test run was cut off in the last row (line 3: invalid JSON")
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_line_before_the_last() {
+        let contents = r#"{"test_group":"alloc","test_count":2,"schema":3}
+{"test":"alloc::a","resu
+{"test":"alloc::b","result":"pass","cycle_count":10}"#;
+        let error = TestSuite::read(contents).unwrap_err();
+        assert!(error.starts_with("line 2: invalid JSON"));
+    }
+
+    #[test]
+    fn rejects_listing_and_missing_header() {
+        let listing = r#"{"test_group":"alloc","test_count":0,"schema":3,"mode":"list"}"#;
+        assert_eq!(
+            TestSuite::read(listing).unwrap_err(),
+            "file is a test listing, not a test run"
+        );
+        assert_eq!(
+            TestSuite::read("").unwrap_err(),
+            "missing test group header"
+        );
+    }
+}
@@ -0,0 +1,429 @@
+//! Rendering of kunit JSONL result files into a single, self-contained HTML report, for reviewing
+//! the results of several architectures side by side.
+//!
+//! Each test group is shown as a tree of modules (the qualified test names split at `::`, as
+//! `split_module_path` splits module paths in the kernel), with a column per architecture holding
+//! the result of each test and a bar showing its time relative to the slowest test of that
+//! architecture. Modules containing a failure are expanded. The details of every failure follow
+//! the tree. The report has no scripts or external resources, so it can be uploaded as a CI
+//! artifact and opened anywhere.
+
+use crate::event::{Outcome, TestResult, TestSuite};
+use crate::junit::escape;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+
+/// The results of one architecture: the test suites read from its JSONL files.
+#[derive(Clone, Debug)]
+pub struct ArchResults {
+    /// The name of the architecture (or any other label for the run), e.g. `x86_64`.
+    pub arch: String,
+    pub suites: Vec<TestSuite>,
+}
+
+/// Writes an HTML report comparing the results of the given architectures.
+///
+/// Cycle counts are converted to times with the `cycle_frequency` in Hz if given, and otherwise
+/// with the frequency reported in each group header; without either, cycle counts are shown.
+pub fn write_report<W: Write>(
+    out: &mut W,
+    archs: &[ArchResults],
+    cycle_frequency: Option<u64>,
+) -> io::Result<()> {
+    let runs: Vec<Run> = archs
+        .iter()
+        .map(|arch| Run::new(arch, cycle_frequency))
+        .collect();
+
+    // the module tree of every test group, over the tests of all architectures
+    let mut groups: BTreeMap<&str, Module> = BTreeMap::new();
+    for arch in archs {
+        for suite in &arch.suites {
+            let tree = groups.entry(&suite.group.test_group).or_default();
+            let tests = suite.results.iter().map(|result| result.test.as_str());
+            for test in tests.chain(suite.unfinished.as_deref()) {
+                tree.insert(test);
+            }
+        }
+    }
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>kunit test report</title>")?;
+    writeln!(out, "<style>{STYLE}</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>kunit test report</h1>")?;
+    write_totals(out, &runs)?;
+
+    for (&group, tree) in &groups {
+        writeln!(out, "<section>")?;
+        writeln!(out, "<h2>{}</h2>", escape(group))?;
+        for run in &runs {
+            match run.suites.get(group) {
+                Some(suite) => {
+                    if let Some(reason) = suite.incomplete() {
+                        writeln!(
+                            out,
+                            "<p class=\"incomplete\">{}: {}</p>",
+                            escape(run.arch),
+                            escape(&reason)
+                        )?;
+                    }
+                }
+                None => writeln!(
+                    out,
+                    "<p class=\"missing\">{}: no results for this test group</p>",
+                    escape(run.arch)
+                )?,
+            }
+        }
+        write_module(out, &runs, group, "", tree)?;
+        writeln!(out, "</section>")?;
+    }
+
+    write_failures(out, &runs)?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+/// The results of one architecture, indexed for rendering.
+struct Run<'a> {
+    arch: &'a str,
+    suites: HashMap<&'a str, &'a TestSuite>,
+    /// The status of every test, by test group and qualified name.
+    tests: HashMap<(&'a str, &'a str), Status<'a>>,
+    /// The details of every failure, in the order they are listed at the end of the report.
+    failures: Vec<(&'a str, String)>,
+    /// The largest cycle count of any test, which is the full width of a timing bar.
+    max_cycles: u64,
+}
+
+/// The result of one test on one architecture.
+#[derive(Clone, Copy)]
+enum Status<'a> {
+    Result {
+        result: &'a TestResult,
+        cycle_frequency: Option<u64>,
+        /// The index of the failure details, if the test failed.
+        failure: Option<usize>,
+    },
+    /// The test started but never reported a result.
+    Crashed { failure: usize },
+}
+
+impl Status<'_> {
+    fn class(&self) -> &'static str {
+        match self {
+            Status::Result { result, .. } => result.result.as_str(),
+            Status::Crashed { .. } => "crash",
+        }
+    }
+
+    fn is_failure(&self) -> bool {
+        match self {
+            Status::Result { result, .. } => result.result.is_failure(),
+            Status::Crashed { .. } => true,
+        }
+    }
+}
+
+impl<'a> Run<'a> {
+    fn new(arch: &'a ArchResults, cycle_frequency: Option<u64>) -> Self {
+        let mut run = Run {
+            arch: &arch.arch,
+            suites: HashMap::new(),
+            tests: HashMap::new(),
+            failures: Vec::new(),
+            max_cycles: 0,
+        };
+        for suite in &arch.suites {
+            let group = suite.group.test_group.as_str();
+            run.suites.insert(group, suite);
+            for result in &suite.results {
+                let failure = result.result.is_failure().then(|| {
+                    run.failures.push((&result.test, result.failure_details()));
+                    run.failures.len() - 1
+                });
+                let status = Status::Result {
+                    result,
+                    cycle_frequency: cycle_frequency.or(suite.group.cycle_frequency),
+                    failure,
+                };
+                run.tests.insert((group, &result.test), status);
+                run.max_cycles = run.max_cycles.max(result.cycle_count);
+            }
+            if let Some(test) = &suite.unfinished {
+                let details = "test started but never completed (crash or hang)\n".to_string();
+                run.failures.push((test, details));
+                let status = Status::Crashed {
+                    failure: run.failures.len() - 1,
+                };
+                run.tests.insert((group, test), status);
+            }
+        }
+        run
+    }
+}
+
+/// A module of a test group, with its submodules and tests.
+#[derive(Default)]
+struct Module<'a> {
+    modules: BTreeMap<&'a str, Module<'a>>,
+    /// The qualified names of the tests directly in this module.
+    tests: BTreeSet<&'a str>,
+}
+
+impl<'a> Module<'a> {
+    fn insert(&mut self, test: &'a str) {
+        let mut module = self;
+        let mut path = test.split("::").peekable();
+        while let Some(part) = path.next() {
+            if path.peek().is_none() {
+                break;
+            }
+            module = module.modules.entry(part).or_default();
+        }
+        module.tests.insert(test);
+    }
+
+    /// Every test in this module and its submodules.
+    fn all_tests(&self) -> Vec<&'a str> {
+        let mut tests: Vec<&str> = self.tests.iter().copied().collect();
+        for module in self.modules.values() {
+            tests.extend(module.all_tests());
+        }
+        tests
+    }
+}
+
+/// Writes the totals of every architecture.
+fn write_totals<W: Write>(out: &mut W, runs: &[Run]) -> io::Result<()> {
+    writeln!(out, "<table class=\"totals\">")?;
+    writeln!(
+        out,
+        "<tr><th>Architecture</th><th>Groups</th><th>Passed</th><th>Failed</th><th>Timed out</th><th>Crashed</th><th>Ignored</th><th>Filtered</th></tr>"
+    )?;
+    for run in runs {
+        let count = |class: &str| {
+            run.tests
+                .values()
+                .filter(|status| status.class() == class)
+                .count()
+        };
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td class=\"pass\">{}</td><td class=\"fail\">{}</td><td class=\"timeout\">{}</td><td class=\"crash\">{}</td><td class=\"ignore\">{}</td><td class=\"filtered\">{}</td></tr>",
+            escape(run.arch),
+            run.suites.len(),
+            count(Outcome::Pass.as_str()),
+            count(Outcome::Fail.as_str()),
+            count(Outcome::Timeout.as_str()),
+            count("crash"),
+            count(Outcome::Ignore.as_str()),
+            count(Outcome::Filtered.as_str()),
+        )?;
+    }
+    writeln!(out, "</table>")
+}
+
+/// Writes a module as a collapsible element, expanded if any of its tests failed on any
+/// architecture, with a table of its tests followed by its submodules.
+fn write_module<W: Write>(
+    out: &mut W,
+    runs: &[Run],
+    group: &str,
+    name: &str,
+    module: &Module,
+) -> io::Result<()> {
+    // the root of a test group is not a module of its own
+    if name.is_empty() {
+        return write_module_contents(out, runs, group, module);
+    }
+
+    let tests = module.all_tests();
+    let failed = runs.iter().any(|run| {
+        tests.iter().any(|&test| {
+            run.tests
+                .get(&(group, test))
+                .is_some_and(|status| status.is_failure())
+        })
+    });
+    let open = if failed { " open" } else { "" };
+    writeln!(out, "<details class=\"module\"{open}>")?;
+    write!(out, "<summary><span class=\"name\">{}</span>", escape(name))?;
+    for run in runs {
+        let statuses: Vec<&Status> = tests
+            .iter()
+            .filter_map(|&test| run.tests.get(&(group, test)))
+            .collect();
+        let count = |class: &str| {
+            statuses
+                .iter()
+                .filter(|status| status.class() == class)
+                .count()
+        };
+        let failed = statuses.iter().filter(|status| status.is_failure()).count();
+        let (class, label) = if statuses.is_empty() {
+            ("missing", "no results".to_string())
+        } else if failed > 0 {
+            ("fail", format!("{failed} failed"))
+        } else {
+            ("pass", format!("{} passed", count(Outcome::Pass.as_str())))
+        };
+        write!(
+            out,
+            " <span class=\"badge {class}\">{}: {label}</span>",
+            escape(run.arch)
+        )?;
+    }
+    writeln!(out, "</summary>")?;
+    write_module_contents(out, runs, group, module)?;
+    writeln!(out, "</details>")
+}
+
+/// Writes the tests of a module, and its submodules.
+fn write_module_contents<W: Write>(
+    out: &mut W,
+    runs: &[Run],
+    group: &str,
+    module: &Module,
+) -> io::Result<()> {
+    if !module.tests.is_empty() {
+        writeln!(out, "<table class=\"tests\">")?;
+        write!(out, "<tr><th>Test</th>")?;
+        for run in runs {
+            write!(out, "<th>{}</th>", escape(run.arch))?;
+        }
+        writeln!(out, "</tr>")?;
+        for &test in &module.tests {
+            let name = test.rsplit("::").next().unwrap_or(test);
+            write!(
+                out,
+                "<tr><td class=\"name\" title=\"{}\">{}</td>",
+                escape(test),
+                escape(name)
+            )?;
+            for (index, run) in runs.iter().enumerate() {
+                write_status(out, index, run, run.tests.get(&(group, test)))?;
+            }
+            writeln!(out, "</tr>")?;
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    for (name, submodule) in &module.modules {
+        write_module(out, runs, group, name, submodule)?;
+    }
+    Ok(())
+}
+
+/// Writes the result of one test on one architecture, as a table cell with a timing bar.
+fn write_status<W: Write>(
+    out: &mut W,
+    index: usize,
+    run: &Run,
+    status: Option<&Status>,
+) -> io::Result<()> {
+    let Some(status) = status else {
+        return writeln!(out, "<td class=\"missing\">-</td>");
+    };
+
+    let label = match status {
+        Status::Result { result, .. } => result.result.as_str(),
+        Status::Crashed { .. } => "crash",
+    };
+    write!(out, "<td class=\"{}\">", status.class())?;
+    match status {
+        Status::Result {
+            failure: Some(failure),
+            ..
+        }
+        | Status::Crashed { failure } => {
+            write!(out, "<a href=\"#failure-{index}-{failure}\">{label}</a>")?
+        }
+        Status::Result { failure: None, .. } => write!(out, "{label}")?,
+    }
+
+    if let Status::Result {
+        result,
+        cycle_frequency,
+        ..
+    } = status
+        && !matches!(result.result, Outcome::Ignore | Outcome::Filtered)
+    {
+        let width = match run.max_cycles {
+            0 => 0.0,
+            max_cycles => result.cycle_count as f64 * 100.0 / max_cycles as f64,
+        };
+        write!(
+            out,
+            "<span class=\"bar\"><span style=\"width: {width:.1}%\"></span></span><span class=\"time\">{}</span>",
+            format_time(result.cycle_count, *cycle_frequency)
+        )?;
+    }
+    writeln!(out, "</td>")
+}
+
+/// Writes the details of every failure, grouped by architecture.
+fn write_failures<W: Write>(out: &mut W, runs: &[Run]) -> io::Result<()> {
+    if runs.iter().all(|run| run.failures.is_empty()) {
+        return Ok(());
+    }
+
+    writeln!(out, "<section>")?;
+    writeln!(out, "<h2>Failures</h2>")?;
+    for (index, run) in runs.iter().enumerate() {
+        if run.failures.is_empty() {
+            continue;
+        }
+        writeln!(out, "<h3>{}</h3>", escape(run.arch))?;
+        for (failure, (test, details)) in run.failures.iter().enumerate() {
+            writeln!(
+                out,
+                "<div class=\"failure\" id=\"failure-{index}-{failure}\"><h4>{}</h4><pre>{}</pre></div>",
+                escape(test),
+                escape(details.trim_end())
+            )?;
+        }
+    }
+    writeln!(out, "</section>")
+}
+
+/// Formats a cycle count as a time, if the cycle counter frequency is known.
+fn format_time(cycles: u64, cycle_frequency: Option<u64>) -> String {
+    let Some(frequency) = cycle_frequency.filter(|&frequency| frequency != 0) else {
+        return format!("{cycles} cycles");
+    };
+    let seconds = cycles as f64 / frequency as f64;
+    if seconds >= 1.0 {
+        format!("{seconds:.2} s")
+    } else if seconds >= 1e-3 {
+        format!("{:.2} ms", seconds * 1e3)
+    } else {
+        format!("{:.2} µs", seconds * 1e6)
+    }
+}
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; padding-bottom: 0.2em; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { padding: 0.2em 0.8em; text-align: left; border-bottom: 1px solid #eee; }
+td.name { font-family: ui-monospace, monospace; }
+details.module { margin: 0.3em 0 0.3em 1.2em; }
+details.module > summary { cursor: pointer; font-family: ui-monospace, monospace; }
+.badge { font-family: system-ui, sans-serif; font-size: 0.8em; padding: 0 0.4em; border-radius: 0.3em; }
+.badge.pass { background: #e3f4e3; }
+.badge.fail { background: #fbe1e1; }
+.pass { color: #1a7f37; }
+.fail, .timeout, .crash { color: #cf222e; font-weight: bold; }
+.ignore, .filtered, .missing { color: #888; }
+.incomplete { color: #cf222e; }
+.bar { display: inline-block; width: 6em; height: 0.6em; margin: 0 0.5em; background: #eee; }
+.bar > span { display: block; height: 100%; background: #54aeff; }
+.time { font-size: 0.8em; color: #555; font-weight: normal; }
+.failure pre { background: #f6f8fa; padding: 0.8em; overflow-x: auto; }
+";
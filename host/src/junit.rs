@@ -0,0 +1,207 @@
+//! Conversion of kunit JSONL result files into a JUnit XML report, for CI systems and dashboards
+//! which read JUnit rather than JSONL (GitLab, Jenkins, ...).
+//!
+//! Every file (one test group) becomes a `<testsuite>` named after the test group, with a
+//! `<testcase>` per test result, named by the qualified test name. Failed and timed-out tests have
+//! a `<failure>` with the panic location and message (or the CPU exception and register dump),
+//! ignored tests have a `<skipped>`, and a test which started but never reported a result (a crash
+//! or hang) has an `<error>`. Filtered tests are left out, as libtest does. Times are converted
+//! from cycle counts using the cycle counter frequency from the group header.
+
+use crate::event::{Outcome, TestResult, TestSuite};
+use std::io::{self, Write};
+
+/// Writes a JUnit XML report of the given test suites.
+///
+/// Cycle counts are converted to seconds with the `cycle_frequency` in Hz if given, and otherwise
+/// with the frequency reported in each group header; without either, times are left out.
+pub fn write_report<W: Write>(
+    out: &mut W,
+    suites: &[TestSuite],
+    cycle_frequency: Option<u64>,
+) -> io::Result<()> {
+    let counts: Vec<Counts> = suites.iter().map(Counts::of).collect();
+    let mut total = Counts::default();
+    for counts in &counts {
+        total.tests += counts.tests;
+        total.failures += counts.failures;
+        total.errors += counts.errors;
+        total.skipped += counts.skipped;
+    }
+    let times: Vec<Option<f64>> = suites
+        .iter()
+        .map(|suite| {
+            let frequency = cycle_frequency.or(suite.group.cycle_frequency)?;
+            let cycles = suite.results.iter().map(|result| result.cycle_count).sum();
+            seconds(cycles, frequency)
+        })
+        .collect();
+    let total_time = times.iter().copied().sum::<Option<f64>>();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    write!(out, "<testsuites name=\"kunit\"")?;
+    total.write_attributes(out)?;
+    write_time(out, total_time)?;
+    writeln!(out, ">")?;
+
+    for ((suite, counts), time) in suites.iter().zip(&counts).zip(times) {
+        let frequency = cycle_frequency.or(suite.group.cycle_frequency);
+        write!(
+            out,
+            "  <testsuite name=\"{}\"",
+            escape(&suite.group.test_group)
+        )?;
+        counts.write_attributes(out)?;
+        write_time(out, time)?;
+        writeln!(out, ">")?;
+
+        for result in &suite.results {
+            if result.result != Outcome::Filtered {
+                write_testcase(out, suite, result, frequency)?;
+            }
+        }
+        if let Some(test) = &suite.unfinished {
+            writeln!(
+                out,
+                "    <testcase name=\"{}\" classname=\"{}\">",
+                escape(test),
+                escape(&suite.group.test_group)
+            )?;
+            writeln!(
+                out,
+                "      <error message=\"test started but never completed (crash or hang)\" type=\"crash\"/>"
+            )?;
+            writeln!(out, "    </testcase>")?;
+        }
+        if let Some(reason) = suite.incomplete() {
+            writeln!(out, "    <system-err>{}</system-err>", escape(&reason))?;
+        }
+        writeln!(out, "  </testsuite>")?;
+    }
+
+    writeln!(out, "</testsuites>")
+}
+
+/// The test counts of a `<testsuite>` or `<testsuites>` element.
+#[derive(Clone, Copy, Debug, Default)]
+struct Counts {
+    tests: u64,
+    failures: u64,
+    errors: u64,
+    skipped: u64,
+}
+
+impl Counts {
+    fn of(suite: &TestSuite) -> Counts {
+        let mut counts = Counts::default();
+        for result in &suite.results {
+            match result.result {
+                Outcome::Filtered => continue,
+                Outcome::Fail | Outcome::Timeout => counts.failures += 1,
+                Outcome::Ignore => counts.skipped += 1,
+                Outcome::Pass => {}
+            }
+            counts.tests += 1;
+        }
+        if suite.unfinished.is_some() {
+            counts.tests += 1;
+            counts.errors += 1;
+        }
+        counts
+    }
+
+    fn write_attributes<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(
+            out,
+            " tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\"",
+            self.tests, self.failures, self.errors, self.skipped
+        )
+    }
+}
+
+/// Writes one `<testcase>` element.
+fn write_testcase<W: Write>(
+    out: &mut W,
+    suite: &TestSuite,
+    result: &TestResult,
+    cycle_frequency: Option<u64>,
+) -> io::Result<()> {
+    write!(
+        out,
+        "    <testcase name=\"{}\" classname=\"{}\"",
+        escape(&result.test),
+        escape(&suite.group.test_group)
+    )?;
+    write_time(
+        out,
+        cycle_frequency.and_then(|frequency| seconds(result.cycle_count, frequency)),
+    )?;
+
+    match result.result {
+        Outcome::Pass | Outcome::Filtered => return writeln!(out, "/>"),
+        Outcome::Ignore => {
+            writeln!(out, ">")?;
+            writeln!(out, "      <skipped/>")?;
+        }
+        Outcome::Timeout => {
+            writeln!(out, ">")?;
+            let message = match result.timeout_ms {
+                Some(timeout_ms) => format!("test exceeded its timeout of {timeout_ms} ms"),
+                None => "test exceeded its timeout".into(),
+            };
+            writeln!(
+                out,
+                "      <failure message=\"{}\" type=\"timeout\"/>",
+                escape(&message)
+            )?;
+        }
+        Outcome::Fail => {
+            writeln!(out, ">")?;
+            let message = result.message.as_deref().unwrap_or("test failed");
+            let kind = result.exception.as_deref().unwrap_or("panic");
+            write!(
+                out,
+                "      <failure message=\"{}\" type=\"{}\">",
+                escape(message.lines().next().unwrap_or("")),
+                escape(kind)
+            )?;
+            write!(out, "{}", escape(&result.failure_details()))?;
+            writeln!(out, "</failure>")?;
+        }
+    }
+    writeln!(out, "    </testcase>")
+}
+
+/// Converts a cycle count into seconds, if the frequency is known.
+fn seconds(cycles: u64, frequency: u64) -> Option<f64> {
+    (frequency != 0).then(|| cycles as f64 / frequency as f64)
+}
+
+/// Writes the `time` attribute, if the time is known.
+fn write_time<W: Write>(out: &mut W, time: Option<f64>) -> io::Result<()> {
+    match time {
+        Some(time) => write!(out, " time=\"{time:.6}\""),
+        None => Ok(()),
+    }
+}
+
+/// Escapes text for use in XML (or HTML) content or a (double-quoted) attribute value. Characters which
+/// XML 1.0 cannot represent at all (most control characters) are written as `\u{..}` escapes.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\0'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {
+                escaped.extend(c.escape_unicode());
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,24 @@
+//! Host-side companion of the kunit test harness.
+//!
+//! The `kunit` binary boots a test image in QEMU, decodes the JSONL events written by the kernel
+//! and prints a libtest-style report, exiting with a status that reflects the test results. It can
+//! be used as a cargo `runner`, so that `cargo test` runs kernel tests like host tests. With
+//! `kunit junit` and `kunit html`, it converts JSONL result files into a JUnit XML report (see
+//! [`junit`]) or an HTML report comparing architectures (see [`html`]), and `kunit diff` compares
+//! two test runs to find regressions (see [`diff`]).
+//!
+//! The `kunit-validate` binary checks the result files collected by `k1` in CI (see
+//! [`validate`]).
+
+pub mod args;
+pub mod diff;
+pub mod event;
+pub mod html;
+pub mod junit;
+pub mod qemu;
+pub mod report;
+pub mod validate;
+
+/// The newest version of the JSONL schema (`"schema"` in the group header) which this crate
+/// understands.
+pub use kunit_events::SCHEMA_VERSION;
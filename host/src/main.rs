@@ -0,0 +1,519 @@
+use kunit_host::diff::{self, RunResults, Thresholds};
+use kunit_host::event::{self, Event, TestSuite};
+use kunit_host::html::{self, ArchResults};
+use kunit_host::junit;
+use kunit_host::qemu::{Arch, QemuConfig};
+use kunit_host::report::Reporter;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: kunit <COMMAND> [ARGS]...
+
+Commands:
+  run    boot a test image in QEMU and report the test results
+  junit  convert JSONL result files into a JUnit XML report
+  html   render JSONL result files of one or more architectures into an HTML report
+  diff   compare two test runs and report regressions
+
+Run 'kunit <COMMAND> --help' for the options of a command.";
+
+const RUN_USAGE: &str = "\
+Usage: kunit run [OPTIONS] <IMAGE> [TEST ARGS]...
+
+Boots a kunit test image in QEMU and reports the test results.
+
+Options:
+  --arch <ARCH>        x86_64 or aarch64 (detected from ELF images, x86_64 otherwise)
+  --qemu <PATH>        the QEMU binary (default: qemu-system-<arch>)
+  --bios <PATH>        firmware to boot with, e.g. OVMF
+  --qemu-arg <ARG>     an extra QEMU argument (may be repeated)
+  --timeout <SECONDS>  kill QEMU if it has not exited after this many seconds
+  --jsonl <PATH>       also write the raw JSONL events to this file
+
+Test arguments are libtest-style (filters, --skip, --exact, --list, --shuffle, --shuffle-seed)
+or kunit harness arguments (kunit.key=value). As a cargo runner:
+
+  [target.'cfg(target_os = \"none\")']
+  runner = \"kunit run\"
+
+Exit status: 0 if all tests passed, 101 if any test failed, 1 if the run did not complete.";
+
+const JUNIT_USAGE: &str = "\
+Usage: kunit junit [OPTIONS] <FILE>...
+
+Converts kunit JSONL result files (e.g. .k1/x86_64/testing/testing-*.jsonl) into a JUnit XML
+report, with one test suite per test group.
+
+Options:
+  --output <PATH>          write the report to this file instead of stdout
+  --cycle-frequency <HZ>   convert cycle counts to times with this frequency, instead of the one
+                           reported by the kernel";
+
+const HTML_USAGE: &str = "\
+Usage: kunit html [OPTIONS] <[ARCH=]PATH>...
+
+Renders kunit JSONL result files into a self-contained HTML report, comparing the results of
+each architecture side by side. Each PATH is a result file or a directory of testing-*.jsonl
+files; its architecture is given as ARCH=PATH, or taken from the path (.k1/<arch>/testing).
+
+  kunit html --output report.html .k1/x86_64/testing .k1/aarch64/testing
+
+Options:
+  --output <PATH>          write the report to this file instead of stdout
+  --cycle-frequency <HZ>   convert cycle counts to times with this frequency, instead of the one
+                           reported by the kernel";
+
+const DIFF_USAGE: &str = "\
+Usage: kunit diff [OPTIONS] <OLD> <NEW>
+
+Compares two test runs and reports the tests which newly fail, newly pass, disappeared, were
+added, or became slower or faster. OLD and NEW are each a JSONL result file, a results.json, or a
+directory of testing-*.jsonl files (e.g. .k1/x86_64/testing). A results.json, which is used for a
+directory without JSONL files, only lists the failed and ignored tests.
+
+Options:
+  --threshold <PERCENT>   the relative change of cycle count above which a test which passed in
+                          both runs is reported as slower or faster (default: 20)
+  --min-cycles <CYCLES>   the absolute change of cycle count above which it is reported
+                          (default: 10000)
+  --fail-on-regression    exit with status 101 if a test newly fails or became slower
+
+Exit status: 0 on success, 101 for a regression (with --fail-on-regression), 1 for any other
+error (invalid arguments or unreadable test runs).";
+
+/// The options of `kunit run`.
+struct RunOptions {
+    arch: Option<Arch>,
+    qemu: Option<PathBuf>,
+    bios: Option<PathBuf>,
+    qemu_args: Vec<OsString>,
+    timeout: Option<Duration>,
+    jsonl: Option<PathBuf>,
+    image: PathBuf,
+    test_args: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = args.first().map(String::as_str);
+    if let Some("-h" | "--help") = args.get(1).map(String::as_str) {
+        match command {
+            Some("run") => println!("{RUN_USAGE}"),
+            Some("junit") => println!("{JUNIT_USAGE}"),
+            Some("html") => println!("{HTML_USAGE}"),
+            Some("diff") => println!("{DIFF_USAGE}"),
+            _ => println!("{USAGE}"),
+        }
+        return ExitCode::SUCCESS;
+    }
+    let result = match command {
+        Some("run") => parse_run_options(&args[1..]).and_then(run),
+        Some("junit") => junit(&args[1..]),
+        Some("html") => html(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        _ => Err(format!("expected a command\n\n{USAGE}")),
+    };
+
+    match result {
+        Ok(exit_code) => ExitCode::from(exit_code),
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn parse_run_options(args: &[String]) -> Result<RunOptions, String> {
+    let mut arch = None;
+    let mut qemu = None;
+    let mut bios = None;
+    let mut qemu_args = Vec::new();
+    let mut timeout = None;
+    let mut jsonl = None;
+
+    let mut args = args.iter();
+    let image = loop {
+        let Some(arg) = args.next() else {
+            return Err(format!("missing test image\n\n{RUN_USAGE}"));
+        };
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--arch" => arch = Some(value()?.parse()?),
+            "--qemu" => qemu = Some(PathBuf::from(value()?)),
+            "--bios" => bios = Some(PathBuf::from(value()?)),
+            "--qemu-arg" => qemu_args.push(OsString::from(value()?)),
+            "--timeout" => {
+                let seconds = value()?;
+                let seconds: u64 = seconds
+                    .parse()
+                    .map_err(|_| format!("invalid timeout '{seconds}'"))?;
+                timeout = Some(Duration::from_secs(seconds));
+            }
+            "--jsonl" => jsonl = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option {arg}\n\n{RUN_USAGE}"));
+            }
+            _ => break PathBuf::from(arg),
+        }
+    };
+
+    Ok(RunOptions {
+        arch,
+        qemu,
+        bios,
+        qemu_args,
+        timeout,
+        jsonl,
+        image,
+        test_args: args.cloned().collect(),
+    })
+}
+
+fn run(options: RunOptions) -> Result<u8, String> {
+    let image_error = |error: io::Error| format!("{}: {error}", options.image.display());
+    let arch = match options.arch {
+        Some(arch) => arch,
+        None => Arch::detect(&options.image)
+            .map_err(image_error)?
+            .unwrap_or(Arch::X86_64),
+    };
+
+    let (harness_args, unsupported) = kunit_host::args::harness_args(&options.test_args)?;
+    for arg in unsupported {
+        eprintln!("warning: ignoring test argument '{arg}', which has no kernel equivalent");
+    }
+
+    let config = QemuConfig {
+        arch,
+        image: options.image.clone(),
+        qemu: options.qemu,
+        bios: options.bios,
+        harness_args,
+        extra_args: options.qemu_args,
+    };
+    let mut command = config.command().map_err(image_error)?;
+    let mut child = command
+        .spawn()
+        .map_err(|error| format!("failed to start {:?}: {error}", command.get_program()))?;
+
+    let mut jsonl = match &options.jsonl {
+        Some(path) => {
+            Some(File::create(path).map_err(|error| format!("{}: {error}", path.display()))?)
+        }
+        None => None,
+    };
+
+    // read on another thread, so that a hung kernel can be killed after the timeout
+    let stdout = child.stdout.take().expect("QEMU stdout is piped");
+    let (lines, received) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdout = BufReader::new(stdout);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match stdout.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line);
+                    if lines.send(text.trim_end().to_string()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let mut reporter = Reporter::new(io::stdout().lock());
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let mut timed_out = false;
+    loop {
+        let line = match deadline {
+            Some(deadline) => {
+                received.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => received.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let line = match line {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => {
+                timed_out = true;
+                let _ = child.kill();
+                break;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        match event::parse_line(&line) {
+            Ok(event) => {
+                if let Some(jsonl) = &mut jsonl {
+                    writeln!(jsonl, "{line}").map_err(|error| error.to_string())?;
+                }
+                if let Event::Group(group) | Event::ListGroup(group) = &event
+                    && group.schema > kunit_host::SCHEMA_VERSION
+                {
+                    eprintln!(
+                        "warning: the kernel writes schema version {}, this runner reads up to version {}",
+                        group.schema,
+                        kunit_host::SCHEMA_VERSION
+                    );
+                }
+                reporter.event(&event)
+            }
+            Err(_) => reporter.output(&line),
+        }
+        .map_err(|error| error.to_string())?;
+    }
+
+    let status = child.wait().map_err(|error| error.to_string())?;
+    let exit_status = if timed_out {
+        reporter.abort(format!(
+            "QEMU did not exit within {} seconds and was killed",
+            options.timeout.unwrap_or_default().as_secs()
+        ));
+        None
+    } else {
+        status.code()
+    };
+    let result = reporter
+        .finish(exit_status)
+        .map_err(|error| error.to_string())?;
+    Ok(result.exit_code())
+}
+
+/// The options of `kunit junit` and `kunit html`.
+struct ReportOptions {
+    output: Option<PathBuf>,
+    cycle_frequency: Option<u64>,
+    inputs: Vec<String>,
+}
+
+fn parse_report_options(args: &[String], usage: &str) -> Result<ReportOptions, String> {
+    let mut output = None;
+    let mut cycle_frequency = None;
+    let mut inputs = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "--cycle-frequency" => {
+                let hz = value()?;
+                let hz: u64 = hz
+                    .parse()
+                    .map_err(|_| format!("invalid cycle frequency '{hz}'"))?;
+                cycle_frequency = Some(hz);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}\n\n{usage}")),
+            _ => inputs.push(arg.clone()),
+        }
+    }
+    if inputs.is_empty() {
+        return Err(format!("missing result files\n\n{usage}"));
+    }
+
+    Ok(ReportOptions {
+        output,
+        cycle_frequency,
+        inputs,
+    })
+}
+
+/// Reads the test suite of a JSONL result file.
+fn load_suite(path: &Path) -> Result<TestSuite, String> {
+    let file_error = |error: String| format!("{}: {error}", path.display());
+    let contents = std::fs::read_to_string(path).map_err(|error| file_error(error.to_string()))?;
+    let suite = TestSuite::read(&contents).map_err(file_error)?;
+    if let Some(error) = &suite.truncated {
+        eprintln!(
+            "warning: {}: ignoring the cut-off last row ({error})",
+            path.display()
+        );
+    }
+    Ok(suite)
+}
+
+/// Reads the test suite of a JSONL result file, warning if its times cannot be determined.
+fn read_suite(path: &Path, cycle_frequency: Option<u64>) -> Result<TestSuite, String> {
+    let suite = load_suite(path)?;
+    if cycle_frequency.is_none() && suite.group.cycle_frequency.is_none() {
+        eprintln!(
+            "warning: {} does not report the cycle counter frequency, so test times are not known",
+            path.display()
+        );
+    }
+    Ok(suite)
+}
+
+/// Writes a report to the output file, or to stdout if there is none.
+fn write_output(
+    output: Option<&Path>,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<u8, String> {
+    match output {
+        Some(path) => File::create(path)
+            .and_then(|mut file| write(&mut file))
+            .map_err(|error| format!("{}: {error}", path.display()))?,
+        None => write(&mut io::stdout().lock()).map_err(|error| error.to_string())?,
+    }
+    Ok(0)
+}
+
+fn junit(args: &[String]) -> Result<u8, String> {
+    let options = parse_report_options(args, JUNIT_USAGE)?;
+    let suites = options
+        .inputs
+        .iter()
+        .map(|path| read_suite(Path::new(path), options.cycle_frequency))
+        .collect::<Result<Vec<_>, _>>()?;
+    write_output(options.output.as_deref(), |mut out| {
+        junit::write_report(&mut out, &suites, options.cycle_frequency)
+    })
+}
+
+fn html(args: &[String]) -> Result<u8, String> {
+    let options = parse_report_options(args, HTML_USAGE)?;
+    let mut archs: Vec<ArchResults> = Vec::new();
+    for input in &options.inputs {
+        // ARCH=PATH, or the architecture is part of the path, as in .k1/<arch>/testing
+        let (arch, path) = match input.split_once('=') {
+            Some((arch, path)) => (arch.to_string(), Path::new(path)),
+            None => {
+                let path = Path::new(input);
+                let arch = path
+                    .components()
+                    .rev()
+                    .find_map(|component| component.as_os_str().to_str()?.parse::<Arch>().ok())
+                    .map_or("results".to_string(), |arch| arch.to_string());
+                (arch, path)
+            }
+        };
+
+        let files = if path.is_dir() {
+            kunit_host::validate::jsonl_files(path)
+                .map_err(|error| format!("{}: {error}", path.display()))?
+        } else {
+            vec![path.to_path_buf()]
+        };
+        let index = match archs.iter().position(|results| results.arch == arch) {
+            Some(index) => index,
+            None => {
+                archs.push(ArchResults {
+                    arch,
+                    suites: Vec::new(),
+                });
+                archs.len() - 1
+            }
+        };
+        for file in files {
+            let suite = read_suite(&file, options.cycle_frequency)?;
+            archs[index].suites.push(suite);
+        }
+    }
+
+    write_output(options.output.as_deref(), |mut out| {
+        html::write_report(&mut out, &archs, options.cycle_frequency)
+    })
+}
+
+fn diff(args: &[String]) -> Result<u8, String> {
+    let mut thresholds = Thresholds::default();
+    let mut fail_on_regression = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--threshold" => {
+                let percent = value()?;
+                thresholds.percent = percent
+                    .parse()
+                    .ok()
+                    .filter(|percent: &f64| *percent >= 0.0)
+                    .ok_or_else(|| format!("invalid threshold '{percent}'"))?;
+            }
+            "--min-cycles" => {
+                let cycles = value()?;
+                thresholds.cycles = cycles
+                    .parse()
+                    .map_err(|_| format!("invalid cycle count '{cycles}'"))?;
+            }
+            "--fail-on-regression" => fail_on_regression = true,
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option {arg}\n\n{DIFF_USAGE}"));
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [old, new] = paths.as_slice() else {
+        return Err(format!(
+            "expected the old and the new test run\n\n{DIFF_USAGE}"
+        ));
+    };
+
+    let diffs = diff::diff(&read_run(old)?, &read_run(new)?, thresholds);
+    diff::write_report(&mut io::stdout().lock(), &diffs).map_err(|error| error.to_string())?;
+    let regressed = diffs.iter().any(|diff| diff.is_regression());
+    Ok(if fail_on_regression && regressed {
+        diff::EXIT_CODE_REGRESSION
+    } else {
+        0
+    })
+}
+
+/// Reads the results of a test run from a JSONL file, a `results.json`, or a directory with
+/// either.
+fn read_run(path: &Path) -> Result<RunResults, String> {
+    let path_error = |error: String| format!("{}: {error}", path.display());
+    let mut run = RunResults::default();
+
+    let files = if path.is_dir() {
+        let files = kunit_host::validate::jsonl_files(path)
+            .map_err(|error| path_error(error.to_string()))?;
+        if files.is_empty() {
+            vec![path.join(kunit_host::validate::RESULTS_FILE)]
+        } else {
+            files
+        }
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    for file in files {
+        if file.file_name() == Some(kunit_host::validate::RESULTS_FILE.as_ref()) {
+            let file_error = |error: String| format!("{}: {error}", file.display());
+            let contents =
+                std::fs::read_to_string(&file).map_err(|error| file_error(error.to_string()))?;
+            let results: serde_json::Value = serde_json::from_str(&contents)
+                .map_err(|error| file_error(format!("invalid JSON ({error})")))?;
+            run.add_results_json(&results).map_err(file_error)?;
+        } else {
+            run.add_suite(&load_suite(&file)?);
+        }
+    }
+    Ok(run)
+}
@@ -0,0 +1,161 @@
+//! Building the QEMU command line for a test image.
+//!
+//! The kernel's output sink (the debug console by default) is connected to QEMU's stdout, and the
+//! harness arguments are passed through the fw_cfg file `opt/kunit/args`, as read by the kernel
+//! at boot.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The exit statuses for the kunit exit codes (`(code << 1) | 1`), from `isa-debug-exit` on
+/// x86_64 and from semihosting on aarch64.
+pub const EXIT_STATUS_SUCCESS: i32 = 33;
+pub const EXIT_STATUS_FAILED: i32 = 35;
+pub const EXIT_STATUS_HARNESS_ERROR: i32 = 37;
+
+/// The architecture of a test image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    /// Reads the architecture from the ELF header of the given file, if it is an ELF file for a
+    /// supported architecture.
+    pub fn detect(path: &Path) -> io::Result<Option<Arch>> {
+        let mut header = [0u8; 20];
+        let mut file = File::open(path)?;
+        if file.read(&mut header)? < header.len() || &header[..4] != b"\x7fELF" {
+            return Ok(None);
+        }
+
+        // e_machine, in the byte order given by EI_DATA
+        let machine = match header[5] {
+            2 => u16::from_be_bytes([header[18], header[19]]),
+            _ => u16::from_le_bytes([header[18], header[19]]),
+        };
+        Ok(match machine {
+            62 => Some(Arch::X86_64),
+            183 => Some(Arch::Aarch64),
+            _ => None,
+        })
+    }
+
+    /// The default QEMU binary for the architecture.
+    pub fn qemu_binary(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "qemu-system-x86_64",
+            Arch::Aarch64 => "qemu-system-aarch64",
+        }
+    }
+}
+
+impl std::str::FromStr for Arch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x86_64" => Ok(Arch::X86_64),
+            "aarch64" => Ok(Arch::Aarch64),
+            _ => Err(format!(
+                "unsupported architecture '{s}' (expected x86_64 or aarch64)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+        })
+    }
+}
+
+/// The QEMU configuration for one test run.
+#[derive(Clone, Debug)]
+pub struct QemuConfig {
+    pub arch: Arch,
+    /// The test image: an ELF kernel (booted with `-kernel`), an ISO (`-cdrom`) or a raw disk
+    /// image (anything else).
+    pub image: PathBuf,
+    /// The QEMU binary, if not the default for the architecture.
+    pub qemu: Option<PathBuf>,
+    /// The firmware image (e.g. OVMF or the aarch64 EFI firmware), if any.
+    pub bios: Option<PathBuf>,
+    /// The harness arguments, e.g. `kunit.filter=alloc::*`. They are passed to the kernel
+    /// separated by spaces, so they must not contain whitespace (see [`crate::args::harness_args`]).
+    pub harness_args: Vec<String>,
+    /// Additional QEMU arguments, appended after the ones set by the runner.
+    pub extra_args: Vec<OsString>,
+}
+
+impl QemuConfig {
+    /// Builds the QEMU command, with the output sink on stdout.
+    pub fn command(&self) -> io::Result<Command> {
+        let qemu = match &self.qemu {
+            Some(qemu) => qemu.as_os_str(),
+            None => self.arch.qemu_binary().as_ref(),
+        };
+        let mut command = Command::new(qemu);
+        command.args(["-display", "none", "-no-reboot"]);
+
+        match self.arch {
+            Arch::X86_64 => {
+                command.args(["-debugcon", "stdio", "-serial", "none"]);
+                command.args(["-device", "isa-debug-exit,iobase=0xf4,iosize=0x04"]);
+            }
+            Arch::Aarch64 => {
+                // the PL011 is the debug console on aarch64
+                command.args(["-machine", "virt", "-cpu", "cortex-a72"]);
+                command.args(["-serial", "stdio"]);
+                // the kernel exits through semihosting, which carries the exit status
+                command.arg("-semihosting");
+            }
+        }
+
+        if let Some(bios) = &self.bios {
+            command.arg("-bios").arg(bios);
+        }
+
+        let is_elf = Arch::detect(&self.image)?.is_some();
+        let extension = self
+            .image
+            .extension()
+            .and_then(|extension| extension.to_str());
+        if is_elf {
+            command.arg("-kernel").arg(&self.image);
+        } else if extension == Some("iso") {
+            command.arg("-cdrom").arg(&self.image);
+        } else {
+            let mut drive = OsString::from("format=raw,file=");
+            drive.push(escape_option(self.image.as_os_str()));
+            command.arg("-drive").arg(drive);
+        }
+
+        if !self.harness_args.is_empty() {
+            let args = self.harness_args.join(" ");
+            let mut fw_cfg = OsString::from("name=opt/kunit/args,string=");
+            fw_cfg.push(escape_option(args.as_ref()));
+            command.arg("-fw_cfg").arg(fw_cfg);
+        }
+
+        command.args(&self.extra_args);
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        Ok(command)
+    }
+}
+
+/// Escapes a value inside a QEMU option list, where commas must be doubled.
+fn escape_option(value: &std::ffi::OsStr) -> OsString {
+    value.to_string_lossy().replace(',', ",,").into()
+}
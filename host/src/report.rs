@@ -0,0 +1,365 @@
+//! A live, libtest-style report of the events of a test run.
+
+use crate::event::{Event, Group, Outcome, Summary, TestResult};
+use std::io::{self, Write};
+use std::time::Instant;
+
+/// The overall result of a test run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RunResult {
+    /// All tests passed or were ignored (or the tests were listed).
+    Passed,
+    /// At least one test failed or timed out.
+    Failed,
+    /// The run did not complete, so the results cannot be trusted.
+    Incomplete(String),
+}
+
+impl RunResult {
+    /// The process exit code for the result: 0, 101 for test failures (as for libtest), or 1 if
+    /// the run did not complete.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            RunResult::Passed => 0,
+            RunResult::Failed => 101,
+            RunResult::Incomplete(_) => 1,
+        }
+    }
+}
+
+/// Prints each event as it arrives, and the failures and totals at the end of the run.
+pub struct Reporter<W: Write> {
+    out: W,
+    started_at: Instant,
+    group: Option<Group>,
+    listing: bool,
+    listed: u64,
+    /// The test which has started but not yet reported a result.
+    running: Option<String>,
+    /// The totals counted from the result rows so far.
+    counted: Summary,
+    failures: Vec<TestResult>,
+    summary: Option<Summary>,
+    /// Why the run is incomplete, if the events cannot show it.
+    aborted: Option<String>,
+}
+
+impl<W: Write> Reporter<W> {
+    /// Creates a reporter which prints to the given output.
+    pub fn new(out: W) -> Self {
+        Reporter {
+            out,
+            started_at: Instant::now(),
+            group: None,
+            listing: false,
+            listed: 0,
+            running: None,
+            counted: Summary::default(),
+            failures: Vec::new(),
+            summary: None,
+            aborted: None,
+        }
+    }
+
+    /// Reports one event.
+    pub fn event(&mut self, event: &Event) -> io::Result<()> {
+        match event {
+            Event::Group(group) => {
+                writeln!(self.out)?;
+                let plural = if group.test_count == 1 { "" } else { "s" };
+                writeln!(
+                    self.out,
+                    "running {} test{plural} in {}",
+                    group.test_count, group.test_group
+                )?;
+                if let Some(shuffle_seed) = group.shuffle_seed {
+                    writeln!(self.out, "shuffle seed: {shuffle_seed}")?;
+                }
+                self.group = Some(group.clone());
+            }
+            Event::ListGroup(group) => {
+                self.listing = true;
+                self.group = Some(group.clone());
+            }
+            Event::Listing(listing) => {
+                self.listed += 1;
+                writeln!(self.out, "{}: test", listing.test)?;
+            }
+            Event::Start { test } => {
+                self.running = Some(test.clone());
+            }
+            Event::Result(result) => {
+                self.running = None;
+                self.counted.record(result);
+                let status = match result.result {
+                    Outcome::Pass => "ok",
+                    Outcome::Fail => "FAILED",
+                    Outcome::Ignore => "ignored",
+                    Outcome::Timeout => "FAILED (timeout)",
+                    // libtest does not list filtered tests
+                    Outcome::Filtered => return Ok(()),
+                };
+                writeln!(self.out, "test {} ... {status}", result.test)?;
+                if result.result.is_failure() {
+                    self.failures.push(result.clone());
+                }
+            }
+            Event::Summary(summary) => {
+                self.summary = Some(summary.clone());
+            }
+        }
+        self.out.flush()
+    }
+
+    /// Reports a line of output which is not an event, such as a message printed by the kernel.
+    pub fn output(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.out, "{line}")?;
+        self.out.flush()
+    }
+
+    /// Marks the run as incomplete for a reason which the events cannot show, such as QEMU being
+    /// killed after a timeout.
+    pub fn abort(&mut self, reason: String) {
+        self.aborted = Some(reason);
+    }
+
+    /// Prints the failures and totals, and determines the result of the run from the events and
+    /// the QEMU exit status (if QEMU exited on its own).
+    pub fn finish(mut self, exit_status: Option<i32>) -> io::Result<RunResult> {
+        if self.listing {
+            writeln!(self.out)?;
+            writeln!(self.out, "{} tests, 0 benchmarks", self.listed)?;
+            return Ok(match self.group {
+                Some(group) if group.test_count == self.listed && self.aborted.is_none() => {
+                    RunResult::Passed
+                }
+                _ => RunResult::Incomplete("the test listing was cut off".into()),
+            });
+        }
+
+        self.write_failures()?;
+
+        let result = self.result(exit_status);
+        let status = match result {
+            RunResult::Passed => "ok",
+            _ => "FAILED",
+        };
+        let summary = self.summary.as_ref().unwrap_or(&self.counted);
+        writeln!(
+            self.out,
+            "test result: {status}. {} passed; {} failed; {} ignored; 0 measured; {} filtered out; finished in {:.2}s",
+            summary.passed,
+            summary.failed + summary.timed_out,
+            summary.ignored,
+            summary.filtered,
+            self.started_at.elapsed().as_secs_f64()
+        )?;
+        if let RunResult::Incomplete(reason) = &result {
+            writeln!(self.out, "error: {reason}")?;
+        }
+        writeln!(self.out)?;
+        Ok(result)
+    }
+
+    fn result(&self, exit_status: Option<i32>) -> RunResult {
+        if let Some(reason) = &self.aborted {
+            return RunResult::Incomplete(reason.clone());
+        }
+        if exit_status == Some(crate::qemu::EXIT_STATUS_HARNESS_ERROR) {
+            return RunResult::Incomplete("the test harness reported an internal error".into());
+        }
+        let Some(group) = &self.group else {
+            return RunResult::Incomplete("the kernel wrote no test group header".into());
+        };
+        if let Some(test) = &self.running {
+            return RunResult::Incomplete(format!(
+                "test {test} started but never completed (crash or hang)"
+            ));
+        }
+        if group.schema >= 3 && self.summary.is_none() {
+            return RunResult::Incomplete("the test run was cut off before the summary".into());
+        }
+        if self.counted.total() != group.test_count {
+            return RunResult::Incomplete(format!(
+                "expected {} test results, got {}",
+                group.test_count,
+                self.counted.total()
+            ));
+        }
+
+        if self.failures.is_empty() && exit_status != Some(crate::qemu::EXIT_STATUS_FAILED) {
+            RunResult::Passed
+        } else {
+            RunResult::Failed
+        }
+    }
+
+    /// Prints the details of every failed test, as libtest does.
+    fn write_failures(&mut self) -> io::Result<()> {
+        if self.failures.is_empty() {
+            writeln!(self.out)?;
+            return Ok(());
+        }
+
+        writeln!(self.out)?;
+        writeln!(self.out, "failures:")?;
+        writeln!(self.out)?;
+        for failure in &self.failures {
+            writeln!(self.out, "---- {} ----", failure.test)?;
+            writeln!(self.out, "{}", failure.failure_details())?;
+        }
+
+        writeln!(self.out)?;
+        writeln!(self.out, "failures:")?;
+        for failure in &self.failures {
+            writeln!(self.out, "    {}", failure.test)?;
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::parse_line;
+    use crate::qemu::{EXIT_STATUS_FAILED, EXIT_STATUS_HARNESS_ERROR, EXIT_STATUS_SUCCESS};
+
+    const GROUP: &str = r#"{"test_group":"alloc","test_count":2,"schema":3}"#;
+    const PASS_A: &str = r#"{"test":"alloc::a","result":"pass","cycle_count":10}"#;
+    const PASS_B: &str = r#"{"test":"alloc::b","result":"pass","cycle_count":20}"#;
+    const FAIL_B: &str = r#"{"test":"alloc::b","result":"fail","cycle_count":20,"location":"src/alloc.rs:3:9","message":"boom"}"#;
+    const SUMMARY_PASSED: &str = r#"{"summary":{"passed":2,"failed":0,"ignored":0,"timed_out":0,"filtered":0,"total_cycles":30,"complete":true}}"#;
+    const SUMMARY_FAILED: &str = r#"{"summary":{"passed":1,"failed":1,"ignored":0,"timed_out":0,"filtered":0,"total_cycles":30,"complete":true}}"#;
+
+    /// Reports the given rows, and returns the result of the run and the printed report.
+    fn run(rows: &[&str], exit_status: Option<i32>) -> (RunResult, String) {
+        let mut out = Vec::new();
+        let mut reporter = Reporter::new(&mut out);
+        for row in rows {
+            reporter.event(&parse_line(row).unwrap()).unwrap();
+        }
+        let result = reporter.finish(exit_status).unwrap();
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn passed() {
+        let (result, out) = run(
+            &[GROUP, PASS_A, PASS_B, SUMMARY_PASSED],
+            Some(EXIT_STATUS_SUCCESS),
+        );
+        assert_eq!(result, RunResult::Passed);
+        assert_eq!(result.exit_code(), 0);
+        assert!(out.contains("test alloc::a ... ok"));
+        assert!(out.contains("test result: ok. 2 passed; 0 failed"));
+    }
+
+    #[test]
+    fn failed() {
+        let (result, out) = run(
+            &[GROUP, PASS_A, FAIL_B, SUMMARY_FAILED],
+            Some(EXIT_STATUS_FAILED),
+        );
+        assert_eq!(result, RunResult::Failed);
+        assert_eq!(result.exit_code(), 101);
+        assert!(out.contains("---- alloc::b ----\npanicked at src/alloc.rs:3:9:\nboom\n"));
+        assert!(out.contains("test result: FAILED. 1 passed; 1 failed"));
+    }
+
+    #[test]
+    fn failed_exit_status_fails_the_run() {
+        let (result, _) = run(
+            &[GROUP, PASS_A, PASS_B, SUMMARY_PASSED],
+            Some(EXIT_STATUS_FAILED),
+        );
+        assert_eq!(result, RunResult::Failed);
+    }
+
+    #[test]
+    fn harness_error_is_incomplete() {
+        let (result, out) = run(
+            &[GROUP, PASS_A, PASS_B, SUMMARY_PASSED],
+            Some(EXIT_STATUS_HARNESS_ERROR),
+        );
+        assert_eq!(
+            result,
+            RunResult::Incomplete("the test harness reported an internal error".into())
+        );
+        assert_eq!(result.exit_code(), 1);
+        assert!(out.contains("error: the test harness reported an internal error"));
+    }
+
+    #[test]
+    fn missing_summary_is_incomplete() {
+        let (result, _) = run(&[GROUP, PASS_A, PASS_B], None);
+        assert_eq!(
+            result,
+            RunResult::Incomplete("the test run was cut off before the summary".into())
+        );
+    }
+
+    #[test]
+    fn schema_1_needs_no_summary() {
+        let group = r#"{"test_group":"alloc","test_count":2}"#;
+        let (result, _) = run(&[group, PASS_A, PASS_B], Some(EXIT_STATUS_SUCCESS));
+        assert_eq!(result, RunResult::Passed);
+    }
+
+    #[test]
+    fn crash_in_a_test_is_incomplete() {
+        let start = r#"{"event":"start","test":"alloc::b"}"#;
+        let (result, _) = run(&[GROUP, PASS_A, start], None);
+        assert_eq!(
+            result,
+            RunResult::Incomplete(
+                "test alloc::b started but never completed (crash or hang)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn missing_results_are_incomplete() {
+        let (result, _) = run(&[GROUP, PASS_A, SUMMARY_PASSED], Some(EXIT_STATUS_SUCCESS));
+        assert_eq!(
+            result,
+            RunResult::Incomplete("expected 2 test results, got 1".into())
+        );
+    }
+
+    #[test]
+    fn missing_group_is_incomplete() {
+        let (result, _) = run(&[], None);
+        assert_eq!(
+            result,
+            RunResult::Incomplete("the kernel wrote no test group header".into())
+        );
+    }
+
+    #[test]
+    fn aborted_run_is_incomplete() {
+        let mut out = Vec::new();
+        let mut reporter = Reporter::new(&mut out);
+        reporter.event(&parse_line(GROUP).unwrap()).unwrap();
+        reporter.abort("QEMU timed out".into());
+        assert_eq!(
+            reporter.finish(None).unwrap(),
+            RunResult::Incomplete("QEMU timed out".into())
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let group = r#"{"test_group":"alloc","test_count":1,"schema":3,"mode":"list"}"#;
+        let listing = r#"{"test":"alloc::a","module":"alloc","name":"a","ignore":false,"should_panic":false}"#;
+        let (result, out) = run(&[group, listing], Some(EXIT_STATUS_SUCCESS));
+        assert_eq!(result, RunResult::Passed);
+        assert!(out.contains("alloc::a: test\n\n1 tests, 0 benchmarks"));
+
+        let (result, _) = run(&[group], Some(EXIT_STATUS_SUCCESS));
+        assert_eq!(
+            result,
+            RunResult::Incomplete("the test listing was cut off".into())
+        );
+    }
+}
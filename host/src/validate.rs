@@ -0,0 +1,506 @@
+//! Validation of the test result files collected by `k1` in `.k1/<arch>/testing`: one
+//! `testing-<group>.jsonl` file per test group, and the `results.json` totals.
+//!
+//! A run is valid if every file is well-formed and complete (a header, one result row per test,
+//! no test which started without a result, and a summary row from schema version 3 on) and no
+//! test failed. Rows are decoded with [`crate::event`], so they are checked against the same
+//! definitions that the kernel writes them with. Files from before the schema was versioned
+//! (version 1) are still accepted, along with the result spellings that other writers used then.
+
+use crate::event::{self, Event, Outcome, ParseError, Summary};
+use kunit_events::field;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// The name of the totals file.
+pub const RESULTS_FILE: &str = "results.json";
+
+/// The outcome of validating a testing directory.
+#[derive(Clone, Debug, Default)]
+pub struct Validation {
+    /// Every problem found, each prefixed with the file it was found in.
+    pub failures: Vec<String>,
+    /// The totals from `results.json`, if it could be read.
+    pub totals: Option<Totals>,
+    /// The ignored tests listed in `results.json`.
+    pub ignored_tests: Vec<String>,
+    /// The test groups of all valid files.
+    pub groups: BTreeSet<String>,
+}
+
+impl Validation {
+    /// Whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The totals from `results.json`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Totals {
+    pub passed: u64,
+    pub failed: u64,
+    pub ignored: u64,
+}
+
+/// Validates the `testing-*.jsonl` files and `results.json` in the given directory.
+pub fn validate_dir(testing_dir: &Path) -> Validation {
+    let mut validation = Validation::default();
+    if !testing_dir.is_dir() {
+        let failure = format!("{} was not created", testing_dir.display());
+        validation.failures.push(failure);
+        return validation;
+    }
+
+    let files = match jsonl_files(testing_dir) {
+        Ok(files) if !files.is_empty() => files,
+        Ok(_) => {
+            let failure = format!(
+                "No testing-*.jsonl files were produced in {}",
+                testing_dir.display()
+            );
+            validation.failures.push(failure);
+            return validation;
+        }
+        Err(error) => {
+            let failure = format!("{}: {error}", testing_dir.display());
+            validation.failures.push(failure);
+            return validation;
+        }
+    };
+
+    let results_path = testing_dir.join(RESULTS_FILE);
+    let results = match fs::read_to_string(&results_path) {
+        Ok(results) => results,
+        Err(_) => {
+            let failure = format!("{} was not produced", results_path.display());
+            validation.failures.push(failure);
+            return validation;
+        }
+    };
+    let results: Value = match serde_json::from_str(&results) {
+        Ok(results) => results,
+        Err(error) => {
+            let failure = format!("{}: invalid JSON ({error})", results_path.display());
+            validation.failures.push(failure);
+            return validation;
+        }
+    };
+    let failed_tests = validate_results(&results_path, &results, &mut validation);
+
+    for path in files {
+        match fs::read_to_string(&path) {
+            Ok(contents) => validate_jsonl(&path, &contents, &mut validation),
+            Err(error) => validation
+                .failures
+                .push(format!("{}: {error}", path.display())),
+        }
+    }
+
+    if let Some(totals) = validation.totals
+        && totals.failed > 0
+    {
+        let failed_tests: BTreeSet<_> = failed_tests.into_iter().collect();
+        let failed_tests: Vec<_> = failed_tests.into_iter().collect();
+        validation.failures.push(format!(
+            "{}: totals.failed={}; failing tests: {}",
+            results_path.display(),
+            totals.failed,
+            failed_tests.join(", ")
+        ));
+    }
+
+    validation
+}
+
+/// The `testing-*.jsonl` files in the given directory, sorted by name.
+pub fn jsonl_files(testing_dir: &Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(testing_dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if name.starts_with("testing-") && name.ends_with(".jsonl") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Checks the totals and test lists of `results.json`, recording the totals and ignored tests.
+/// Returns the failed tests.
+fn validate_results(path: &Path, results: &Value, validation: &mut Validation) -> Vec<String> {
+    let path = path.display();
+    let failures = &mut validation.failures;
+
+    let empty = Map::new();
+    let totals = match results.get("totals").and_then(Value::as_object) {
+        Some(totals) => totals,
+        None => {
+            failures.push(format!("{path}: missing object 'totals'"));
+            &empty
+        }
+    };
+    let mut count = |key: &str| {
+        let value = totals.get(key).and_then(Value::as_u64);
+        if value.is_none() {
+            failures.push(format!(
+                "{path}: totals.{key} must be a non-negative integer"
+            ));
+        }
+        value
+    };
+    let (passed, failed, ignored) = (count("passed"), count("failed"), count("ignored"));
+
+    let mut test_list = |key: &str| -> Vec<String> {
+        let tests = results
+            .get(key)
+            .and_then(Value::as_array)
+            .and_then(|tests| {
+                tests
+                    .iter()
+                    .map(|test| test.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+            });
+        tests.unwrap_or_else(|| {
+            failures.push(format!("{path}: {key} must be an array of strings"));
+            Vec::new()
+        })
+    };
+    let failed_tests = test_list("failed_tests");
+    let ignored_tests = test_list("ignored_tests");
+
+    if failed == Some(0) && !failed_tests.is_empty() {
+        failures.push(format!(
+            "{path}: failed_tests is not empty while totals.failed is 0"
+        ));
+    }
+    if ignored == Some(0) && !ignored_tests.is_empty() {
+        failures.push(format!(
+            "{path}: ignored_tests is not empty while totals.ignored is 0"
+        ));
+    }
+
+    if let (Some(passed), Some(failed), Some(ignored)) = (passed, failed, ignored) {
+        validation.totals = Some(Totals {
+            passed,
+            failed,
+            ignored,
+        });
+    }
+    validation.ignored_tests = ignored_tests;
+    failed_tests
+}
+
+/// Checks one `testing-<group>.jsonl` file.
+pub fn validate_jsonl(path: &Path, contents: &str, validation: &mut Validation) {
+    let display = path.display();
+    let failures = &mut validation.failures;
+
+    let mut lines = contents
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| (index + 1, line));
+    let Some((_, header)) = lines.next() else {
+        failures.push(format!("{display}: file is empty"));
+        return;
+    };
+
+    let group = match event::parse_line(header) {
+        Ok(Event::Group(group)) => group,
+        Ok(Event::ListGroup(_)) => {
+            failures.push(format!("{display}: file is a test listing, not a test run"));
+            return;
+        }
+        Ok(_) => {
+            failures.push(format!("{display}: missing or invalid test_group"));
+            return;
+        }
+        Err(ParseError::Json(error)) => {
+            failures.push(format!("{display}: invalid JSON header ({error})"));
+            return;
+        }
+        Err(
+            ParseError::MissingField(field::TEST_COUNT)
+            | ParseError::InvalidField(field::TEST_COUNT),
+        ) => {
+            failures.push(format!("{display}: missing or invalid test_count"));
+            return;
+        }
+        Err(_) => {
+            failures.push(format!("{display}: missing or invalid test_group"));
+            return;
+        }
+    };
+    if group.test_group.is_empty() {
+        failures.push(format!("{display}: missing or invalid test_group"));
+        return;
+    }
+
+    let file_name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("");
+    let file_group = file_name.strip_prefix("testing-").unwrap_or(file_name);
+    if file_group != group.test_group {
+        failures.push(format!(
+            "{display}: file group '{file_group}' does not match JSON group '{}'",
+            group.test_group
+        ));
+    }
+    validation.groups.insert(group.test_group.clone());
+
+    let mut test_rows = Vec::new();
+    let mut started_test = None;
+    let mut summary: Option<Result<Summary, ParseError>> = None;
+    for (index, line) in lines {
+        let row = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(row)) => row,
+            Ok(_) => Map::new(),
+            Err(error) => {
+                failures.push(format!("{display}: line {index} is invalid JSON ({error})"));
+                continue;
+            }
+        };
+        if summary.is_some() {
+            failures.push(format!("{display}: line {index} follows the summary row"));
+        }
+        if row.contains_key(field::SUMMARY) {
+            summary = Some(match event::parse_row(&row) {
+                Ok(Event::Summary(row)) => Ok(row),
+                Ok(_) => Err(ParseError::UnknownRow),
+                Err(error) => Err(error),
+            });
+            continue;
+        }
+        if row.get(field::EVENT).and_then(Value::as_str) == Some(kunit_events::EVENT_START) {
+            let test = row.get(field::TEST).and_then(Value::as_str);
+            started_test = Some(test.unwrap_or("<unknown>").to_string());
+            continue;
+        }
+        started_test = None;
+        test_rows.push((index, row));
+    }
+
+    if test_rows.len() as u64 != group.test_count {
+        failures.push(format!(
+            "{display}: test_count is {} but file contains {} test rows",
+            group.test_count,
+            test_rows.len()
+        ));
+    }
+    if let Some(test) = started_test {
+        failures.push(format!(
+            "{display}: test '{test}' started but never completed (crash or hang)"
+        ));
+    }
+    if group.schema >= 3 {
+        match summary {
+            Some(Ok(summary)) if summary.complete => {
+                if summary.total() != group.test_count {
+                    failures.push(format!(
+                        "{display}: summary counts {} results but test_count is {}",
+                        summary.total(),
+                        group.test_count
+                    ));
+                }
+            }
+            Some(Err(error)) => {
+                failures.push(format!("{display}: invalid summary row ({error})"));
+            }
+            _ => failures.push(format!(
+                "{display}: missing summary row, the test run did not complete"
+            )),
+        }
+    }
+
+    for (index, row) in test_rows {
+        let test = row
+            .get(field::TEST)
+            .and_then(Value::as_str)
+            .unwrap_or("<unknown>");
+        let outcome = if group.schema >= 2 {
+            match event::parse_row(&row) {
+                Ok(Event::Result(result)) => Ok(result.result),
+                Ok(_) => Err(format!("line {index} is not a test result row")),
+                Err(ParseError::UnknownResult(result)) => {
+                    Err(format!("has unknown result '{result}'"))
+                }
+                Err(error) => Err(format!("line {index}: {error}")),
+            }
+        } else {
+            legacy_outcome(&row)
+        };
+
+        match outcome {
+            Ok(outcome) if outcome.is_failure() => failures.push(format!(
+                "{display}: test '{test}' has failing result '{outcome}'"
+            )),
+            Ok(_) => {}
+            Err(problem) => failures.push(format!("{display}: test '{test}' {problem}")),
+        }
+    }
+}
+
+/// Reads the result of a row from before the schema was versioned, which may be spelled in
+/// various ways, and in a `result`, `status` or `outcome` field.
+fn legacy_outcome(row: &Map<String, Value>) -> Result<Outcome, String> {
+    let result = ["result", "status", "outcome"]
+        .into_iter()
+        .find_map(|key| row.get(key).filter(|value| !value.is_null()));
+    let spelling = match result {
+        Some(Value::String(result)) => result.trim().to_lowercase(),
+        Some(result) => result.to_string(),
+        None => String::new(),
+    };
+
+    match spelling.as_str() {
+        "pass" | "passed" | "ok" | "success" => Ok(Outcome::Pass),
+        "ignore" | "ignored" | "skip" | "skipped" => Ok(Outcome::Ignore),
+        "filtered" => Ok(Outcome::Filtered),
+        "fail" | "failed" | "error" | "panic" => Ok(Outcome::Fail),
+        "timeout" => Ok(Outcome::Timeout),
+        _ => Err(format!("has unknown result '{spelling}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Validates the given file contents as `testing-<group>.jsonl`, and returns the failures.
+    fn validate(group: &str, contents: &str) -> Vec<String> {
+        let mut validation = Validation::default();
+        let path = format!("testing-{group}.jsonl");
+        validate_jsonl(Path::new(&path), contents, &mut validation);
+        validation.failures
+    }
+
+    /// Validates the given `results.json`, and returns the failures.
+    fn validate_totals(results: &str) -> Vec<String> {
+        let mut validation = Validation::default();
+        let results = serde_json::from_str(results).unwrap();
+        validate_results(Path::new(RESULTS_FILE), &results, &mut validation);
+        validation.failures
+    }
+
+    const VALID: &str = r#"{"test_group":"alloc","test_count":2,"schema":3}
+{"event":"start","test":"alloc::a"}
+{"test":"alloc::a","result":"pass","cycle_count":10}
+{"event":"start","test":"alloc::b"}
+{"test":"alloc::b","result":"ignore","cycle_count":0}
+{"summary":{"passed":1,"failed":0,"ignored":1,"timed_out":0,"filtered":0,"total_cycles":10,"complete":true}}
+"#;
+
+    #[test]
+    fn valid_file() {
+        assert_eq!(validate("alloc", VALID), Vec::<String>::new());
+    }
+
+    #[test]
+    fn schema_1_legacy_spellings() {
+        let contents = r#"{"test_group":"alloc","test_count":3}
+{"test":"alloc::a","status":"passed"}
+{"test":"alloc::b","outcome":"skipped"}
+{"test":"alloc::c","result":"OK"}
+"#;
+        assert_eq!(validate("alloc", contents), Vec::<String>::new());
+    }
+
+    #[test]
+    fn schema_1_legacy_failure() {
+        let contents = r#"{"test_group":"alloc","test_count":2}
+{"test":"alloc::a","status":"panic"}
+{"test":"alloc::b","outcome":"bogus"}
+"#;
+        assert_eq!(
+            validate("alloc", contents),
+            [
+                "testing-alloc.jsonl: test 'alloc::a' has failing result 'fail'",
+                "testing-alloc.jsonl: test 'alloc::b' has unknown result 'bogus'",
+            ]
+        );
+    }
+
+    #[test]
+    fn legacy_spellings_need_schema_1() {
+        let contents = VALID.replace(r#""result":"ignore""#, r#""result":"skipped""#);
+        assert_eq!(
+            validate("alloc", &contents),
+            ["testing-alloc.jsonl: test 'alloc::b' has unknown result 'skipped'"]
+        );
+    }
+
+    #[test]
+    fn test_count_mismatch() {
+        let contents = VALID.replace(r#""test_count":2"#, r#""test_count":3"#);
+        let failures = validate("alloc", &contents);
+        assert_eq!(
+            failures[0],
+            "testing-alloc.jsonl: test_count is 3 but file contains 2 test rows"
+        );
+    }
+
+    #[test]
+    fn start_without_result() {
+        let contents = r#"{"test_group":"alloc","test_count":1,"schema":3}
+{"event":"start","test":"alloc::a"}
+"#;
+        let failures = validate("alloc", contents);
+        assert!(
+            failures.contains(
+                &"testing-alloc.jsonl: test 'alloc::a' started but never completed (crash or hang)"
+                    .to_string()
+            )
+        );
+        assert!(failures.contains(
+            &"testing-alloc.jsonl: missing summary row, the test run did not complete".to_string()
+        ));
+    }
+
+    #[test]
+    fn row_after_summary() {
+        let contents = format!(
+            "{VALID}{}\n",
+            r#"{"test":"alloc::c","result":"pass","cycle_count":1}"#
+        );
+        let failures = validate("alloc", &contents);
+        assert!(failures.contains(&"testing-alloc.jsonl: line 7 follows the summary row".into()));
+    }
+
+    #[test]
+    fn file_name_must_match_group() {
+        assert_eq!(
+            validate("mem", VALID),
+            ["testing-mem.jsonl: file group 'mem' does not match JSON group 'alloc'"]
+        );
+    }
+
+    #[test]
+    fn failed_tests_without_failed_total() {
+        let results = r#"{"totals":{"passed":1,"failed":0,"ignored":0},"failed_tests":["alloc::a"],"ignored_tests":[]}"#;
+        assert_eq!(
+            validate_totals(results),
+            ["results.json: failed_tests is not empty while totals.failed is 0"]
+        );
+    }
+
+    #[test]
+    fn invalid_totals() {
+        let results = r#"{"totals":{"passed":-1,"failed":0,"ignored":0},"failed_tests":[],"ignored_tests":[1]}"#;
+        assert_eq!(
+            validate_totals(results),
+            [
+                "results.json: totals.passed must be a non-negative integer",
+                "results.json: ignored_tests must be an array of strings",
+            ]
+        );
+    }
+}
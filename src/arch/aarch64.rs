@@ -1,9 +1,16 @@
-const PSCI_SYSTEM_OFF: u64 = 0x8400_0008;
+mod exceptions;
+mod vectors;
+mod watchdog;
 
-const PL011_BASE: usize = 0x0900_0000;
-const PL011_DR: usize = PL011_BASE + 0x00;
-const PL011_FR: usize = PL011_BASE + 0x18;
-const PL011_FR_TXFF: u32 = 1 << 5;
+pub use exceptions::{arm_exception_handlers, disarm_exception_handlers, init_exception_handler};
+pub use watchdog::{arm_watchdog, disarm_watchdog, init_watchdog};
+
+const SEMIHOSTING_SYS_EXIT_EXTENDED: u64 = 0x20;
+const SEMIHOSTING_ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+const FW_CFG_BASE: usize = 0x0902_0000;
+const FW_CFG_DATA: usize = FW_CFG_BASE + 0x00;
+const FW_CFG_SELECTOR: usize = FW_CFG_BASE + 0x08;
 
 pub fn disable_interrupts() {
     unsafe {
@@ -14,13 +21,34 @@ pub fn disable_interrupts() {
     }
 }
 
+/// Runs `f` with IRQs and FIQs masked, restoring the previous mask afterwards.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let daif: u64;
+    unsafe {
+        core::arch::asm!("mrs {daif}, daif", daif = out(reg) daif, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("msr daifset, #3", options(nomem, nostack, preserves_flags));
+    }
+    let result = f();
+    unsafe {
+        core::arch::asm!("msr daif, {daif}", daif = in(reg) daif, options(nomem, nostack, preserves_flags));
+    }
+    result
+}
+
+/// Exits QEMU through the semihosting `SYS_EXIT_EXTENDED` call, which requires QEMU to run with
+/// `-semihosting`. The exit status is `(exit_code << 1) | 1`, as with `isa-debug-exit` on x86_64,
+/// so the statuses are the same on both architectures.
 pub fn exit(exit_code: u32) -> ! {
-    let _ = exit_code;
+    let block: [u64; 2] = [
+        SEMIHOSTING_ADP_STOPPED_APPLICATION_EXIT,
+        ((exit_code as u64) << 1) | 1,
+    ];
 
     unsafe {
         core::arch::asm!(
-            "hvc #0",
-            in("x0") PSCI_SYSTEM_OFF,
+            "hlt #0xf000",
+            in("x0") SEMIHOSTING_SYS_EXIT_EXTENDED,
+            in("x1") block.as_ptr(),
             options(nostack)
         );
     }
@@ -40,15 +68,62 @@ pub fn read_cycle() -> u64 {
     value
 }
 
-pub fn debug_write(bytes: &[u8]) {
-    use core::ptr::{read_volatile, write_volatile};
+/// Returns the frequency of the cycle counter (the virtual counter) in Hz.
+pub fn cycle_frequency() -> u64 {
+    let value: u64;
+    unsafe {
+        core::arch::asm!("mrs {value}, cntfrq_el0", value = out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
 
-    for &byte in bytes {
-        unsafe {
-            while read_volatile(PL011_FR as *const u32) & PL011_FR_TXFF != 0 {
-                core::arch::asm!("nop", options(nomem, nostack, preserves_flags));
-            }
-            write_volatile(PL011_DR as *mut u32, byte as u32);
-        }
+pub fn read_stack_pointer() -> usize {
+    let value: usize;
+    unsafe {
+        core::arch::asm!("mov {value}, sp", value = out(reg) value, options(nomem, nostack, preserves_flags));
     }
+    value
+}
+
+/// Switches to the given stack pointer and calls `entry` on it, discarding every frame below
+/// the stack pointer. The frame pointer and link register are cleared so that backtraces end
+/// at `entry`.
+///
+/// # Safety
+///
+/// Nothing may still reference the frames below `stack_pointer`, as they will be overwritten.
+pub unsafe fn jump_with_stack(stack_pointer: usize, entry: extern "C" fn() -> !) -> ! {
+    // the stack pointer must always be 16-byte aligned
+    let stack_pointer = stack_pointer & !0xf;
+
+    unsafe {
+        core::arch::asm!(
+            "mov sp, {stack}",
+            "mov x29, xzr",
+            "mov x30, xzr",
+            "blr {entry}",
+            "udf #0",
+            stack = in(reg) stack_pointer,
+            entry = in(reg) entry,
+            options(noreturn)
+        );
+    }
+}
+
+pub fn fw_cfg_select(selector: u16) {
+    // the MMIO selector register is big-endian
+    unsafe {
+        core::ptr::write_volatile(FW_CFG_SELECTOR as *mut u16, selector.to_be());
+    }
+}
+
+pub fn fw_cfg_read_byte() -> u8 {
+    unsafe { core::ptr::read_volatile(FW_CFG_DATA as *const u8) }
+}
+
+/// Writes to the PL011 UART of the QEMU `virt` machine, which serves as the debug console.
+pub fn debug_write(bytes: &[u8]) {
+    use crate::sink::OutputSink;
+
+    crate::sink::Pl011::QEMU_VIRT.write(bytes);
 }
@@ -0,0 +1,124 @@
+//! Handlers for CPU exceptions raised by a test, so that they fail the test instead of bringing
+//! down the whole run.
+//!
+//! Synchronous exceptions and SErrors from the current EL are taken through the harness vector
+//! table, which is installed while the handlers are armed (and handed back to the previous table
+//! while only the watchdog is). The exception type is decoded from the
+//! exception class in `ESR_EL1`. Stack overflows cannot be caught, since the exception frame is
+//! saved on the same stack.
+
+use crate::test::exception::{ExceptionInfo, Fault};
+use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The function to call when a test raises an exception.
+static EXCEPTION_HANDLER: OnceCell<fn(&ExceptionInfo) -> !> = OnceCell::uninit();
+
+/// Whether the exception handlers are currently armed.
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+/// The names of the general purpose registers, in the order they are saved in the frame.
+const REGISTER_NAMES: [&str; 31] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "x29", "x30",
+];
+
+/// The registers saved by the exception entries of the harness vector table.
+#[repr(C)]
+pub(super) struct ExceptionFrame {
+    registers: [u64; 31],
+    sp: u64,
+    pub(super) elr: u64,
+    spsr: u64,
+}
+
+/// Sets the function to call when a test raises an exception. Only the first call has an effect.
+pub fn init_exception_handler(on_exception: fn(&ExceptionInfo) -> !) {
+    EXCEPTION_HANDLER.init_once(|| on_exception);
+}
+
+/// Arms the exception handlers, by installing the harness vector table.
+pub fn arm_exception_handlers() {
+    if !ARMED.swap(true, Ordering::SeqCst) {
+        super::vectors::acquire();
+    }
+}
+
+/// Disarms the exception handlers, if armed. If the watchdog is not armed either, the previous
+/// vector table is restored.
+pub fn disarm_exception_handlers() {
+    if ARMED.swap(false, Ordering::SeqCst) {
+        super::vectors::release();
+    }
+}
+
+/// Called from the synchronous exception and SError vectors, with the saved registers. If the
+/// handlers are not armed (the vector table is only installed for the watchdog), a synchronous
+/// exception is handed back to the previous vector table, and this returns. An SError cannot be
+/// raised again, so the run ends with a harness error instead.
+#[unsafe(no_mangle)]
+extern "C" fn kunit_exception_handler(frame: &mut ExceptionFrame, serror: u64) {
+    let on_exception = match EXCEPTION_HANDLER.get() {
+        Some(on_exception) if ARMED.load(Ordering::SeqCst) => on_exception,
+        _ if serror != 0 => super::exit(crate::qemu::ExitCode::HarnessError as u32),
+        _ => return super::vectors::hand_back_synchronous(frame),
+    };
+    // the handler does not return through `eret`, so the interrupt masks of the test are restored
+    // here, unless the watchdog restores its own from before it was armed
+    let restore_daif = !super::watchdog::is_armed();
+    disarm_exception_handlers();
+    super::disarm_watchdog();
+    if restore_daif {
+        // DAIF is bits 9:6 of SPSR_EL1, at the same position as in the DAIF register
+        let daif = frame.spsr & 0x3c0;
+        unsafe {
+            core::arch::asm!("msr daif, {value}", value = in(reg) daif, options(nomem, nostack, preserves_flags));
+        }
+    }
+
+    let esr: u64;
+    let far: u64;
+    unsafe {
+        core::arch::asm!("mrs {value}, esr_el1", value = out(reg) esr, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mrs {value}, far_el1", value = out(reg) far, options(nomem, nostack, preserves_flags));
+    }
+
+    let (fault, kind, has_address) = if serror != 0 {
+        (Fault::Other, "SError", false)
+    } else {
+        exception_class(esr)
+    };
+
+    let mut info = ExceptionInfo::new(fault, kind, frame.elr);
+    info.address = has_address.then_some(far);
+    info.error_code = Some(esr);
+    for (&name, value) in REGISTER_NAMES.iter().zip(frame.registers) {
+        info.push_register(name, value);
+    }
+    info.push_register("sp", frame.sp);
+    info.push_register("elr_el1", frame.elr);
+    info.push_register("spsr_el1", frame.spsr);
+    info.push_register("esr_el1", esr);
+    info.push_register("far_el1", far);
+
+    on_exception(&info)
+}
+
+/// Decodes the exception class of a synchronous exception, returning its class, its type and
+/// whether `FAR_EL1` holds the faulting address.
+fn exception_class(esr: u64) -> (Fault, &'static str, bool) {
+    match (esr >> 26) & 0x3f {
+        0x00 => (Fault::InvalidOpcode, "undefined instruction", false),
+        0x0e => (Fault::Other, "illegal execution state", false),
+        0x15 => (Fault::Other, "supervisor call", false),
+        0x18 => (Fault::Other, "system register trap", false),
+        0x20 | 0x21 => (Fault::PageFault, "instruction abort", true),
+        0x22 => (Fault::Alignment, "PC alignment fault", true),
+        0x24 | 0x25 => (Fault::PageFault, "data abort", true),
+        0x26 => (Fault::Alignment, "SP alignment fault", false),
+        0x2c => (Fault::FloatingPoint, "floating point exception", false),
+        0x3c => (Fault::Breakpoint, "breakpoint", false),
+        _ => (Fault::Other, "synchronous exception", false),
+    }
+}
@@ -0,0 +1,243 @@
+//! The harness-owned exception vector table, shared by the watchdog and the exception handlers.
+//!
+//! The table is installed in `VBAR_EL1` while at least one of them is armed, and the previous
+//! table is restored once neither is.
+//!
+//! Unlike an IDT entry, a vector is code, and jumping to the previous table would need a free
+//! register while every register still belongs to the interrupted code. So an exception that the
+//! harness does not own is handed back instead: the watchdog and the exception handlers are
+//! disarmed, which restores the previous table, and the exception entry returns to where the
+//! exception was taken. A pending interrupt is then taken again, and a synchronous exception is
+//! raised again, through the previous table. The rest of that test runs without a timeout.
+
+use super::exceptions::ExceptionFrame;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The number of armed users (the watchdog and the exception handlers) of the vector table.
+static USERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The vector table base that was installed before the harness vector table.
+static PREVIOUS_VBAR: AtomicU64 = AtomicU64::new(0);
+
+/// Installs the harness vector table, unless it is already installed. Every call must be paired
+/// with a call to [`release`].
+pub fn acquire() {
+    if USERS.fetch_add(1, Ordering::SeqCst) != 0 {
+        return;
+    }
+
+    unsafe extern "C" {
+        static kunit_vector_table: u8;
+    }
+
+    unsafe {
+        let previous_vbar: u64;
+        core::arch::asm!("mrs {value}, vbar_el1", value = out(reg) previous_vbar, options(nomem, nostack, preserves_flags));
+        PREVIOUS_VBAR.store(previous_vbar, Ordering::SeqCst);
+
+        let vector_table = &raw const kunit_vector_table as u64;
+        core::arch::asm!("msr vbar_el1, {value}", "isb", value = in(reg) vector_table, options(nostack, preserves_flags));
+    }
+}
+
+/// Restores the previous vector table, once the last user has released the harness table.
+pub fn release() {
+    if USERS.fetch_sub(1, Ordering::SeqCst) != 1 {
+        return;
+    }
+
+    let previous_vbar = PREVIOUS_VBAR.load(Ordering::SeqCst);
+    unsafe {
+        core::arch::asm!("msr vbar_el1, {value}", "isb", value = in(reg) previous_vbar, options(nostack, preserves_flags));
+    }
+}
+
+/// Hands an exception that the harness does not own back to the previous vector table, by
+/// disarming both users of the harness table. The exception entry must return afterwards.
+pub(super) fn hand_back() {
+    super::disarm_watchdog();
+    super::disarm_exception_handlers();
+}
+
+/// Hands a synchronous exception back to the previous vector table. The exception is raised again
+/// by the instruction at `ELR_EL1`, so for exception-generating instructions (which return to
+/// the next instruction), `ELR_EL1` is moved back to the instruction itself.
+pub(super) fn hand_back_synchronous(frame: &mut ExceptionFrame) {
+    let esr: u64;
+    unsafe {
+        core::arch::asm!("mrs {value}, esr_el1", value = out(reg) esr, options(nomem, nostack, preserves_flags));
+    }
+    hand_back();
+
+    // SVC, HVC and SMC from AArch32 and AArch64; bit 25 (IL) is set for 32-bit instructions
+    if matches!((esr >> 26) & 0x3f, 0x11 | 0x12 | 0x13 | 0x15 | 0x16 | 0x17) {
+        frame.elr -= if esr & (1 << 25) != 0 { 4 } else { 2 };
+    }
+}
+
+/// Called from the synchronous exception vectors for lower ELs. These belong to the kernel (e.g.
+/// system calls), so they are handed back.
+#[unsafe(no_mangle)]
+extern "C" fn kunit_lower_sync_handler(frame: &mut ExceptionFrame) {
+    hand_back_synchronous(frame);
+}
+
+/// Called from the FIQ vectors. The harness does not use FIQs, so they are handed back while
+/// still pending.
+#[unsafe(no_mangle)]
+extern "C" fn kunit_fiq_handler() {
+    hand_back();
+}
+
+/// Called from the SError vectors for lower ELs. An SError cannot be raised again, so it is
+/// reported as a harness error.
+#[unsafe(no_mangle)]
+extern "C" fn kunit_unexpected_exception() -> ! {
+    super::exit(crate::qemu::ExitCode::HarnessError as u32)
+}
+
+// The vector table has 16 entries of 0x80 bytes each: synchronous, IRQ, FIQ and SError for the
+// current EL with SP_EL0, the current EL with SP_ELx, a lower EL in AArch64 and a lower EL in
+// AArch32. IRQs go to the watchdog, synchronous exceptions and SErrors from the current EL go to
+// the exception handlers, and FIQs and synchronous exceptions from lower ELs are handed back.
+//
+// The exception entries save an `ExceptionFrame` (x0-x30, the interrupted sp, elr_el1 and
+// spsr_el1) and pass it to their handler, which only returns to hand the exception back. The
+// interrupt entries only save the caller-saved registers around their handler.
+core::arch::global_asm!(
+    r#"
+.pushsection .text.kunit_vectors, "ax"
+.balign 0x800
+.global kunit_vector_table
+kunit_vector_table:
+.rept 2
+    .balign 0x80
+    b kunit_sync_entry
+    .balign 0x80
+    b kunit_irq_entry
+    .balign 0x80
+    b kunit_fiq_entry
+    .balign 0x80
+    b kunit_serror_entry
+.endr
+.rept 2
+    .balign 0x80
+    b kunit_lower_sync_entry
+    .balign 0x80
+    b kunit_irq_entry
+    .balign 0x80
+    b kunit_fiq_entry
+    .balign 0x80
+    b kunit_unexpected_exception
+.endr
+
+.macro kunit_save_exception_frame
+    sub sp, sp, #272
+    stp x0, x1, [sp, #0]
+    stp x2, x3, [sp, #16]
+    stp x4, x5, [sp, #32]
+    stp x6, x7, [sp, #48]
+    stp x8, x9, [sp, #64]
+    stp x10, x11, [sp, #80]
+    stp x12, x13, [sp, #96]
+    stp x14, x15, [sp, #112]
+    stp x16, x17, [sp, #128]
+    stp x18, x19, [sp, #144]
+    stp x20, x21, [sp, #160]
+    stp x22, x23, [sp, #176]
+    stp x24, x25, [sp, #192]
+    stp x26, x27, [sp, #208]
+    stp x28, x29, [sp, #224]
+    add x0, sp, #272
+    stp x30, x0, [sp, #240]
+    mrs x0, elr_el1
+    mrs x1, spsr_el1
+    stp x0, x1, [sp, #256]
+    mov x0, sp
+.endm
+
+.macro kunit_interrupt_entry handler
+    sub sp, sp, #192
+    stp x0, x1, [sp, #0]
+    stp x2, x3, [sp, #16]
+    stp x4, x5, [sp, #32]
+    stp x6, x7, [sp, #48]
+    stp x8, x9, [sp, #64]
+    stp x10, x11, [sp, #80]
+    stp x12, x13, [sp, #96]
+    stp x14, x15, [sp, #112]
+    stp x16, x17, [sp, #128]
+    stp x18, x29, [sp, #144]
+    str x30, [sp, #160]
+    mrs x0, elr_el1
+    mrs x1, spsr_el1
+    stp x0, x1, [sp, #176]
+
+    bl \handler
+
+    ldp x0, x1, [sp, #176]
+    msr elr_el1, x0
+    msr spsr_el1, x1
+    ldr x30, [sp, #160]
+    ldp x18, x29, [sp, #144]
+    ldp x16, x17, [sp, #128]
+    ldp x14, x15, [sp, #112]
+    ldp x12, x13, [sp, #96]
+    ldp x10, x11, [sp, #80]
+    ldp x8, x9, [sp, #64]
+    ldp x6, x7, [sp, #48]
+    ldp x4, x5, [sp, #32]
+    ldp x2, x3, [sp, #16]
+    ldp x0, x1, [sp, #0]
+    add sp, sp, #192
+    eret
+.endm
+
+kunit_sync_entry:
+    kunit_save_exception_frame
+    mov x1, #0
+    bl kunit_exception_handler
+    b kunit_exception_return
+
+kunit_serror_entry:
+    kunit_save_exception_frame
+    mov x1, #1
+    bl kunit_exception_handler
+    b kunit_exception_return
+
+kunit_lower_sync_entry:
+    kunit_save_exception_frame
+    bl kunit_lower_sync_handler
+
+// restores the (possibly updated) exception frame, which is on top of the stack
+kunit_exception_return:
+    ldp x0, x1, [sp, #256]
+    msr elr_el1, x0
+    msr spsr_el1, x1
+    ldr x30, [sp, #240]
+    ldp x28, x29, [sp, #224]
+    ldp x26, x27, [sp, #208]
+    ldp x24, x25, [sp, #192]
+    ldp x22, x23, [sp, #176]
+    ldp x20, x21, [sp, #160]
+    ldp x18, x19, [sp, #144]
+    ldp x16, x17, [sp, #128]
+    ldp x14, x15, [sp, #112]
+    ldp x12, x13, [sp, #96]
+    ldp x10, x11, [sp, #80]
+    ldp x8, x9, [sp, #64]
+    ldp x6, x7, [sp, #48]
+    ldp x4, x5, [sp, #32]
+    ldp x2, x3, [sp, #16]
+    ldp x0, x1, [sp, #0]
+    add sp, sp, #272
+    eret
+
+kunit_irq_entry:
+    kunit_interrupt_entry kunit_irq_handler
+
+kunit_fiq_entry:
+    kunit_interrupt_entry kunit_fiq_handler
+.popsection
+"#
+);
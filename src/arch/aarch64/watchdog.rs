@@ -0,0 +1,223 @@
+//! A watchdog for test timeouts, driven by the EL1 virtual generic timer through a GICv2.
+//!
+//! While armed, the harness installs its own exception vector table (restoring the previous one
+//! when disarmed) and routes the virtual timer PPI to the CPU. The GIC is the one configured with
+//! `KlibConfigBuilder::gic` (the QEMU `virt` machine's by default), and its registers are
+//! assumed to be identity mapped. The GIC state and the interrupt masks which the watchdog
+//! changes are saved when it is armed and restored when it is disarmed.
+//!
+//! The timer gets the highest priority while armed, and the priority mask only lets that
+//! priority through, so the kernel's interrupts stay pending until the previous vector table is
+//! back. One that still arrives (with the same priority) is handed back to the kernel.
+
+use crate::watchdog::GicV2;
+use conquer_once::spin::OnceCell;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+const GICD_CTLR: usize = 0x000;
+const GICD_ISENABLER0: usize = 0x100;
+const GICD_ICENABLER0: usize = 0x180;
+const GICD_IPRIORITYR: usize = 0x400;
+
+const GICC_CTLR: usize = 0x00;
+const GICC_PMR: usize = 0x04;
+const GICC_IAR: usize = 0x0c;
+const GICC_EOIR: usize = 0x10;
+const GICC_HPPIR: usize = 0x18;
+
+/// The interrupt ID of the EL1 virtual timer (PPI 11).
+const VIRTUAL_TIMER_INTID: u32 = 27;
+/// Interrupt ID returned by the GIC when there is no pending interrupt.
+const SPURIOUS_INTID: u32 = 1023;
+
+/// The priority of the virtual timer while armed (the highest one).
+const TIMER_PRIORITY: u8 = 0x00;
+/// The priority mask while armed, which only signals interrupts with the timer's priority.
+const ARMED_PRIORITY_MASK: u32 = 0x10;
+
+/// The function to call when the watchdog expires.
+static TIMEOUT_HANDLER: OnceCell<fn() -> !> = OnceCell::uninit();
+
+/// Whether the watchdog is currently armed.
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+/// The GIC state and interrupt masks from before the watchdog was armed.
+static SAVED_STATE: Mutex<Option<SavedState>> = Mutex::new(None);
+
+/// The GIC state and interrupt masks which the watchdog changes.
+#[derive(Clone, Copy)]
+struct SavedState {
+    daif: u64,
+    distributor_control: u32,
+    cpu_interface_control: u32,
+    priority_mask: u32,
+    timer_enabled: bool,
+    timer_priority: u8,
+}
+
+/// Sets the function to call when the watchdog expires. Only the first call has an effect.
+pub fn init_watchdog(on_timeout: fn() -> !) {
+    TIMEOUT_HANDLER.init_once(|| on_timeout);
+}
+
+/// Arms the watchdog to expire after the given number of milliseconds. IRQs are unmasked while
+/// the watchdog is armed. Arming an armed watchdog restarts it.
+pub fn arm_watchdog(timeout_ms: u64) {
+    disarm_watchdog();
+    let daif: u64;
+    unsafe {
+        core::arch::asm!("mrs {value}, daif", value = out(reg) daif, options(nomem, nostack, preserves_flags));
+    }
+    mask_irqs();
+
+    init_gic(gic(), daif);
+    super::vectors::acquire();
+
+    let frequency: u64;
+    let now: u64;
+    unsafe {
+        core::arch::asm!("mrs {value}, cntfrq_el0", value = out(reg) frequency, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mrs {value}, cntvct_el0", value = out(reg) now, options(nomem, nostack, preserves_flags));
+    }
+    let ticks = (frequency / 1000).saturating_mul(timeout_ms.max(1));
+
+    ARMED.store(true, Ordering::SeqCst);
+    unsafe {
+        core::arch::asm!(
+            "msr cntv_cval_el0, {deadline}",
+            "msr cntv_ctl_el0, {enable}",
+            "isb",
+            deadline = in(reg) now.saturating_add(ticks),
+            enable = in(reg) 1u64,
+            options(nostack, preserves_flags)
+        );
+        core::arch::asm!("msr daifclr, #2", options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Disarms the watchdog, if armed. The previous GIC state and vector table are restored, and
+/// then the interrupt masks from before the watchdog was armed. If the watchdog is not armed,
+/// this does nothing.
+pub fn disarm_watchdog() {
+    if !ARMED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    mask_irqs();
+    unsafe {
+        core::arch::asm!(
+            "msr cntv_ctl_el0, xzr",
+            "isb",
+            options(nostack, preserves_flags)
+        );
+    }
+    let daif = restore_gic(gic());
+    super::vectors::release();
+    if let Some(daif) = daif {
+        unsafe {
+            core::arch::asm!("msr daif, {value}", value = in(reg) daif, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+/// Whether the watchdog is currently armed.
+pub(super) fn is_armed() -> bool {
+    ARMED.load(Ordering::SeqCst)
+}
+
+fn mask_irqs() {
+    unsafe {
+        core::arch::asm!("msr daifset, #2", options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// The configured GIC.
+fn gic() -> GicV2 {
+    crate::args::get_klib_config().map_or(GicV2::QEMU_VIRT, |config| config.gic)
+}
+
+fn distributor(gic: GicV2, offset: usize) -> *mut u32 {
+    (gic.distributor + offset) as *mut u32
+}
+
+fn cpu_interface(gic: GicV2, offset: usize) -> *mut u32 {
+    (gic.cpu_interface + offset) as *mut u32
+}
+
+/// The priority register of the virtual timer, one byte per interrupt.
+fn timer_priority(gic: GicV2) -> *mut u8 {
+    (gic.distributor + GICD_IPRIORITYR + VIRTUAL_TIMER_INTID as usize) as *mut u8
+}
+
+/// Saves the GIC state (along with the given interrupt masks), then enables the distributor, the
+/// CPU interface and the virtual timer PPI (with the highest priority) and raises the priority
+/// mask.
+fn init_gic(gic: GicV2, daif: u64) {
+    unsafe {
+        let state = SavedState {
+            daif,
+            distributor_control: read_volatile(distributor(gic, GICD_CTLR)),
+            cpu_interface_control: read_volatile(cpu_interface(gic, GICC_CTLR)),
+            priority_mask: read_volatile(cpu_interface(gic, GICC_PMR)),
+            timer_enabled: read_volatile(distributor(gic, GICD_ISENABLER0))
+                & (1 << VIRTUAL_TIMER_INTID)
+                != 0,
+            timer_priority: read_volatile(timer_priority(gic)),
+        };
+        *SAVED_STATE.lock() = Some(state);
+
+        write_volatile(timer_priority(gic), TIMER_PRIORITY);
+        write_volatile(distributor(gic, GICD_ISENABLER0), 1 << VIRTUAL_TIMER_INTID);
+        write_volatile(distributor(gic, GICD_CTLR), state.distributor_control | 1);
+        write_volatile(cpu_interface(gic, GICC_PMR), ARMED_PRIORITY_MASK);
+        write_volatile(
+            cpu_interface(gic, GICC_CTLR),
+            state.cpu_interface_control | 1,
+        );
+    }
+}
+
+/// Restores the GIC state from before the watchdog was armed, and returns the interrupt masks
+/// from then. The timer must be stopped.
+fn restore_gic(gic: GicV2) -> Option<u64> {
+    let state = SAVED_STATE.lock().take()?;
+
+    unsafe {
+        if !state.timer_enabled {
+            write_volatile(distributor(gic, GICD_ICENABLER0), 1 << VIRTUAL_TIMER_INTID);
+        }
+        write_volatile(timer_priority(gic), state.timer_priority);
+        write_volatile(cpu_interface(gic, GICC_PMR), state.priority_mask);
+        write_volatile(cpu_interface(gic, GICC_CTLR), state.cpu_interface_control);
+        write_volatile(distributor(gic, GICD_CTLR), state.distributor_control);
+    }
+    Some(state.daif)
+}
+
+/// Called from the IRQ vector with all caller-saved registers preserved. Only the virtual timer
+/// is acknowledged; any other interrupt is handed back to the kernel while still pending.
+#[unsafe(no_mangle)]
+extern "C" fn kunit_irq_handler() {
+    let gic = gic();
+    let intid = unsafe { read_volatile(cpu_interface(gic, GICC_HPPIR)) } & 0x3ff;
+    if intid == SPURIOUS_INTID {
+        return;
+    }
+    if intid != VIRTUAL_TIMER_INTID {
+        super::vectors::hand_back();
+        return;
+    }
+
+    // nothing can preempt the timer between the two reads, as it has the highest priority
+    let iar = unsafe { read_volatile(cpu_interface(gic, GICC_IAR)) };
+    unsafe { write_volatile(cpu_interface(gic, GICC_EOIR), iar) };
+
+    if ARMED.load(Ordering::SeqCst) {
+        disarm_watchdog();
+        if let Some(on_timeout) = TIMEOUT_HANDLER.get() {
+            on_timeout();
+        }
+    }
+}
@@ -5,10 +5,20 @@ mod x86_64;
 mod aarch64;
 
 #[cfg(target_arch = "x86_64")]
-pub use x86_64::{debug_write, disable_interrupts, exit, read_cycle};
+pub use x86_64::{
+    arm_exception_handlers, arm_watchdog, cycle_frequency, debug_write, disable_interrupts,
+    disarm_exception_handlers, disarm_watchdog, exit, fw_cfg_read_byte, fw_cfg_select,
+    init_exception_handler, init_watchdog, jump_with_stack, read_cycle, read_stack_pointer,
+    without_interrupts,
+};
 
 #[cfg(target_arch = "aarch64")]
-pub use aarch64::{debug_write, disable_interrupts, exit, read_cycle};
+pub use aarch64::{
+    arm_exception_handlers, arm_watchdog, cycle_frequency, debug_write, disable_interrupts,
+    disarm_exception_handlers, disarm_watchdog, exit, fw_cfg_read_byte, fw_cfg_select,
+    init_exception_handler, init_watchdog, jump_with_stack, read_cycle, read_stack_pointer,
+    without_interrupts,
+};
 
 #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
 compile_error!("kunit currently supports only x86_64 and aarch64 targets");
@@ -1,9 +1,31 @@
+mod exceptions;
+mod idt;
+mod watchdog;
+
+pub use exceptions::{arm_exception_handlers, disarm_exception_handlers, init_exception_handler};
+pub use watchdog::{arm_watchdog, disarm_watchdog, init_watchdog};
+
 use x86_64::instructions::{nop, port::Port};
 
+const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+const PIT_CHANNEL_2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+/// Port B of the keyboard controller, which controls the gate of PIT channel 2 and reports its
+/// output.
+const PIT_CHANNEL_2_GATE: u16 = 0x61;
+/// The time span over which a counter (the TSC or the local APIC timer) is calibrated against the
+/// PIT, in milliseconds.
+const CALIBRATION_MS: u64 = 10;
+
 pub fn disable_interrupts() {
     x86_64::instructions::interrupts::disable();
 }
 
+/// Runs `f` with interrupts disabled, restoring the previous interrupt state afterwards.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    x86_64::instructions::interrupts::without_interrupts(f)
+}
+
 pub fn exit(exit_code: u32) -> ! {
     unsafe {
         let mut port = Port::new(0xf4);
@@ -19,6 +41,99 @@ pub fn read_cycle() -> u64 {
     unsafe { core::arch::x86_64::_rdtsc() }
 }
 
+/// Returns the frequency of the cycle counter (the TSC) in Hz.
+///
+/// If the CPU reports the TSC frequency in CPUID leaf 0x15, that is used. Otherwise (which is the
+/// case in QEMU), the TSC is measured over a few milliseconds of PIT channel 2, so this should be
+/// called sparingly.
+pub fn cycle_frequency() -> u64 {
+    use core::arch::x86_64::__cpuid;
+
+    let max_leaf = __cpuid(0).eax;
+    if max_leaf >= 0x15 {
+        let leaf = __cpuid(0x15);
+        let (denominator, numerator, crystal_hz) = (leaf.eax, leaf.ebx, leaf.ecx);
+        if denominator != 0 && numerator != 0 && crystal_hz != 0 {
+            return crystal_hz as u64 * numerator as u64 / denominator as u64;
+        }
+    }
+
+    measure_rate(read_cycle)
+}
+
+/// Measures the rate of an up counter in Hz, over a few milliseconds of PIT channel 2.
+fn measure_rate(mut read_counter: impl FnMut() -> u64) -> u64 {
+    unsafe {
+        let mut gate: Port<u8> = Port::new(PIT_CHANNEL_2_GATE);
+        let mut channel_2: Port<u8> = Port::new(PIT_CHANNEL_2);
+        let previous_gate = gate.read();
+
+        // enable the channel 2 gate, with the speaker disconnected
+        gate.write((previous_gate & !0x02) | 0x01);
+
+        // channel 2, lobyte/hibyte access, mode 0 (interrupt on terminal count), binary
+        let count = (PIT_FREQUENCY_HZ * CALIBRATION_MS / 1000) as u16;
+        Port::<u8>::new(PIT_COMMAND).write(0xb0);
+        channel_2.write(count as u8);
+        channel_2.write((count >> 8) as u8);
+
+        // the channel 2 output goes high once the count reaches zero
+        let start = read_counter();
+        while gate.read() & 0x20 == 0 {
+            core::hint::spin_loop();
+        }
+        let end = read_counter();
+
+        gate.write(previous_gate);
+        end.wrapping_sub(start) * 1000 / CALIBRATION_MS
+    }
+}
+
+pub fn read_stack_pointer() -> usize {
+    let value: usize;
+    unsafe {
+        core::arch::asm!("mov {value}, rsp", value = out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Switches to the given stack pointer and calls `entry` on it, discarding every frame below
+/// the stack pointer. The frame pointer is cleared so that backtraces end at `entry`.
+///
+/// # Safety
+///
+/// Nothing may still reference the frames below `stack_pointer`, as they will be overwritten.
+pub unsafe fn jump_with_stack(stack_pointer: usize, entry: extern "C" fn() -> !) -> ! {
+    // the ABI requires a 16-byte aligned stack before the call instruction
+    let stack_pointer = stack_pointer & !0xf;
+
+    unsafe {
+        core::arch::asm!(
+            "mov rsp, {stack}",
+            "xor ebp, ebp",
+            "call {entry}",
+            "ud2",
+            stack = in(reg) stack_pointer,
+            entry = in(reg) entry,
+            options(noreturn)
+        );
+    }
+}
+
+pub fn fw_cfg_select(selector: u16) {
+    unsafe {
+        let mut port = Port::new(0x510);
+        port.write(selector);
+    }
+}
+
+pub fn fw_cfg_read_byte() -> u8 {
+    unsafe {
+        let mut port = Port::new(0x511);
+        port.read()
+    }
+}
+
 pub fn debug_write(bytes: &[u8]) {
     unsafe {
         for byte in bytes {
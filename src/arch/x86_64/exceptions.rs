@@ -0,0 +1,178 @@
+//! Handlers for CPU exceptions raised by a test, so that they fail the test instead of bringing
+//! down the whole run.
+//!
+//! The handlers live in the harness IDT, which is loaded while they are armed. Exceptions are
+//! handed to the kernel's IDT while they are not, e.g. for page faults while only the watchdog
+//! runs. The register dump
+//! is limited to what the CPU pushes on the stack (plus `CR2` and `CR3`), since the general
+//! purpose registers are already in use by the handler. Stack overflows cannot be caught, as the
+//! harness has no TSS to switch to a known good stack.
+
+use crate::test::exception::{ExceptionInfo, Fault};
+use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::control::{Cr2, Cr3};
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+
+/// The function to call when a test raises an exception.
+static EXCEPTION_HANDLER: OnceCell<fn(&ExceptionInfo) -> !> = OnceCell::uninit();
+
+/// Whether the exception handlers are currently armed.
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+/// Sets the function to call when a test raises an exception. Only the first call has an effect.
+pub fn init_exception_handler(on_exception: fn(&ExceptionInfo) -> !) {
+    EXCEPTION_HANDLER.init_once(|| on_exception);
+}
+
+/// Arms the exception handlers, by loading the harness IDT.
+pub fn arm_exception_handlers() {
+    if !ARMED.swap(true, Ordering::SeqCst) {
+        super::idt::acquire(super::idt::User::Exceptions);
+    }
+}
+
+/// Disarms the exception handlers, if armed. If the watchdog is not armed either, the previous
+/// IDT is restored.
+pub fn disarm_exception_handlers() {
+    if ARMED.swap(false, Ordering::SeqCst) {
+        super::idt::release(super::idt::User::Exceptions);
+    }
+}
+
+/// Sets the handlers for the CPU exceptions in the harness IDT.
+pub(super) fn set_handlers(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(divide_error_handler);
+    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available
+        .set_handler_fn(device_not_available_handler);
+    idt.double_fault.set_handler_fn(double_fault_handler);
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present
+        .set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault
+        .set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault_handler);
+    idt.page_fault.set_handler_fn(page_fault_handler);
+    idt.x87_floating_point
+        .set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    idt.simd_floating_point
+        .set_handler_fn(simd_floating_point_handler);
+}
+
+/// Reports an exception to the exception handler, which does not return. If the handlers are not
+/// armed, the exception cannot be attributed to a test and the run ends with a harness error.
+fn report(
+    fault: Fault,
+    kind: &'static str,
+    frame: &InterruptStackFrame,
+    error_code: Option<u64>,
+    address: Option<u64>,
+) -> ! {
+    let on_exception = match EXCEPTION_HANDLER.get() {
+        Some(on_exception) if ARMED.load(Ordering::SeqCst) => on_exception,
+        _ => super::exit(crate::qemu::ExitCode::HarnessError as u32),
+    };
+    // the handler does not return through `iretq`, so the interrupt flag of the test is restored
+    // here, unless the watchdog restores its own from before it was armed
+    let restore_interrupts =
+        !super::watchdog::is_armed() && frame.cpu_flags.contains(RFlags::INTERRUPT_FLAG);
+    disarm_exception_handlers();
+    super::disarm_watchdog();
+    if restore_interrupts {
+        x86_64::instructions::interrupts::enable();
+    }
+
+    let mut info = ExceptionInfo::new(fault, kind, frame.instruction_pointer.as_u64());
+    info.address = address;
+    info.error_code = error_code;
+    info.push_register("rip", frame.instruction_pointer.as_u64());
+    info.push_register("rsp", frame.stack_pointer.as_u64());
+    info.push_register("rflags", frame.cpu_flags.bits());
+    info.push_register("cs", frame.code_segment.0 as u64);
+    info.push_register("ss", frame.stack_segment.0 as u64);
+    info.push_register("cr2", Cr2::read_raw());
+    info.push_register("cr3", Cr3::read_raw().0.start_address().as_u64());
+
+    on_exception(&info)
+}
+
+/// Defines an exception handler which reports the exception with the given class and type.
+macro_rules! exception_handler {
+    ($name:ident, $fault:ident, $kind:literal) => {
+        extern "x86-interrupt" fn $name(frame: InterruptStackFrame) {
+            report(Fault::$fault, $kind, &frame, None, None)
+        }
+    };
+    ($name:ident, $fault:ident, $kind:literal, error_code) => {
+        extern "x86-interrupt" fn $name(frame: InterruptStackFrame, error_code: u64) {
+            report(Fault::$fault, $kind, &frame, Some(error_code), None)
+        }
+    };
+}
+
+exception_handler!(divide_error_handler, DivideError, "divide error");
+exception_handler!(breakpoint_handler, Breakpoint, "breakpoint");
+exception_handler!(overflow_handler, Other, "overflow");
+exception_handler!(bound_range_exceeded_handler, Other, "bound range exceeded");
+exception_handler!(invalid_opcode_handler, InvalidOpcode, "invalid opcode");
+exception_handler!(device_not_available_handler, Other, "device not available");
+exception_handler!(invalid_tss_handler, Other, "invalid TSS", error_code);
+exception_handler!(
+    segment_not_present_handler,
+    Other,
+    "segment not present",
+    error_code
+);
+exception_handler!(
+    stack_segment_fault_handler,
+    Other,
+    "stack segment fault",
+    error_code
+);
+exception_handler!(
+    general_protection_fault_handler,
+    GeneralProtection,
+    "general protection fault",
+    error_code
+);
+exception_handler!(
+    x87_floating_point_handler,
+    FloatingPoint,
+    "x87 floating point exception"
+);
+exception_handler!(
+    alignment_check_handler,
+    Alignment,
+    "alignment check",
+    error_code
+);
+exception_handler!(
+    simd_floating_point_handler,
+    FloatingPoint,
+    "SIMD floating point exception"
+);
+
+extern "x86-interrupt" fn double_fault_handler(frame: InterruptStackFrame, error_code: u64) -> ! {
+    report(Fault::Other, "double fault", &frame, Some(error_code), None)
+}
+
+extern "x86-interrupt" fn page_fault_handler(
+    frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    report(
+        Fault::PageFault,
+        "page fault",
+        &frame,
+        Some(error_code.bits()),
+        Some(Cr2::read_raw()),
+    )
+}
@@ -0,0 +1,100 @@
+//! The harness-owned IDT, shared by the watchdog and the exception handlers.
+//!
+//! The IDT is loaded while at least one of them is armed, and the IDT that was loaded before is
+//! restored once neither is. The harness IDT starts out as a copy of the previous IDT and only
+//! the vectors of the armed users are replaced, so every other interrupt and exception still
+//! reaches the kernel's handlers.
+
+use core::mem::size_of;
+use spin::Mutex;
+use x86_64::instructions::{interrupts, tables};
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::structures::DescriptorTablePointer;
+
+/// The size of an IDT entry in long mode.
+const ENTRY_SIZE: usize = 16;
+
+/// A user of the harness IDT, which owns a few of its vectors while armed.
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum User {
+    Watchdog = 1 << 0,
+    Exceptions = 1 << 1,
+}
+
+/// The harness IDT. It is rebuilt whenever a user is armed or disarmed, so it must stay at the
+/// same address while loaded.
+static IDT: Mutex<InterruptDescriptorTable> = Mutex::new(InterruptDescriptorTable::new());
+
+/// The armed users of the harness IDT, and the IDT that was loaded before it.
+static STATE: Mutex<State> = Mutex::new(State {
+    users: 0,
+    previous_idt: None,
+});
+
+struct State {
+    /// A bit set of [`User`]s.
+    users: u8,
+    previous_idt: Option<DescriptorTablePointer>,
+}
+
+/// Loads the harness IDT with the vectors of the given user, unless they are already loaded.
+/// Every call must be paired with a call to [`release`] for the same user.
+pub fn acquire(user: User) {
+    interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        if state.users & user as u8 != 0 {
+            return;
+        }
+        if state.users == 0 {
+            state.previous_idt = Some(tables::sidt());
+        }
+        state.users |= user as u8;
+        load(&state);
+    });
+}
+
+/// Hands the vectors of the given user back to the previous IDT. The previous IDT is restored
+/// once the last user has released the harness IDT.
+pub fn release(user: User) {
+    interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        if state.users & user as u8 == 0 {
+            return;
+        }
+        state.users &= !(user as u8);
+        if state.users != 0 {
+            load(&state);
+        } else if let Some(previous_idt) = state.previous_idt.take() {
+            unsafe { tables::lidt(&previous_idt) };
+        }
+    });
+}
+
+/// Rebuilds the harness IDT for the armed users and loads it. Interrupts must be disabled.
+fn load(state: &State) {
+    let mut idt = IDT.lock();
+    idt.reset();
+
+    if let Some(previous_idt) = &state.previous_idt {
+        let entries = ((previous_idt.limit as usize + 1) / ENTRY_SIZE).min(256);
+        let bytes = (entries * ENTRY_SIZE).min(size_of::<InterruptDescriptorTable>());
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                previous_idt.base.as_ptr::<u8>(),
+                &mut *idt as *mut InterruptDescriptorTable as *mut u8,
+                bytes,
+            );
+        }
+    }
+
+    if state.users & User::Watchdog as u8 != 0 {
+        super::watchdog::set_handlers(&mut idt);
+    }
+    if state.users & User::Exceptions as u8 != 0 {
+        super::exceptions::set_handlers(&mut idt);
+    }
+
+    // the IDT lives in a static, so it stays in place for as long as it is loaded
+    unsafe { idt.load_unsafe() };
+}
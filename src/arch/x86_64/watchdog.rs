@@ -0,0 +1,281 @@
+//! A watchdog for test timeouts, driven by the timer of the local APIC.
+//!
+//! While armed, the harness loads its own IDT (restoring the previous one when disarmed) and runs
+//! the local APIC timer periodically at 1 kHz. The timer registers are saved when the watchdog is
+//! armed and restored when it is disarmed, so a kernel which uses the timer itself gets it back
+//! (restarted from its initial count), and the legacy PIC and PIT are left alone. The local APIC
+//! is accessed through MSRs if the kernel enabled x2APIC mode, and otherwise through its MMIO
+//! registers, which are assumed to be identity mapped (as with the MMIO output sinks). Whether
+//! interrupts were enabled is restored as well.
+
+use conquer_once::spin::OnceCell;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+/// The interrupt vector of the watchdog timer.
+const TIMER_VECTOR: u8 = 0xfe;
+/// The spurious interrupt vector, if the harness has to software-enable the local APIC.
+const SPURIOUS_VECTOR: u8 = 0xff;
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const IA32_TSC_DEADLINE: u32 = 0x6e0;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_GLOBAL_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+/// The MSR of the first local APIC register in x2APIC mode.
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const APIC_EOI: u32 = 0x0b0;
+const APIC_SPURIOUS: u32 = 0x0f0;
+const APIC_IRR: u32 = 0x200;
+const APIC_LVT_TIMER: u32 = 0x320;
+const APIC_INITIAL_COUNT: u32 = 0x380;
+const APIC_CURRENT_COUNT: u32 = 0x390;
+const APIC_DIVIDE_CONFIG: u32 = 0x3e0;
+
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+const LVT_TIMER_MODE_MASK: u32 = 0b11 << 17;
+const LVT_TIMER_TSC_DEADLINE: u32 = 0b10 << 17;
+const DIVIDE_BY_16: u32 = 0b0011;
+
+/// The function to call when the watchdog expires.
+static TIMEOUT_HANDLER: OnceCell<fn() -> !> = OnceCell::uninit();
+
+/// Milliseconds left until the watchdog expires.
+static REMAINING_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Whether the watchdog is currently armed.
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+/// Local APIC timer ticks per millisecond (with a divider of 16), or 0 if not measured yet.
+static TICKS_PER_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Whether the harness software-enabled the local APIC, and so owns the spurious vector.
+static OWNS_SPURIOUS_VECTOR: AtomicBool = AtomicBool::new(false);
+
+/// The local APIC and interrupt state from before the watchdog was armed.
+static SAVED_STATE: Mutex<Option<SavedState>> = Mutex::new(None);
+
+/// The local APIC and interrupt state which the watchdog changes.
+#[derive(Clone, Copy)]
+struct SavedState {
+    interrupts_enabled: bool,
+    apic_base: u64,
+    spurious: u32,
+    lvt_timer: u32,
+    divide_config: u32,
+    initial_count: u32,
+    tsc_deadline: u64,
+}
+
+/// The registers of the local APIC, in the mode the kernel enabled it in.
+#[derive(Clone, Copy)]
+enum LocalApic {
+    X2Apic,
+    XApic(usize),
+}
+
+impl LocalApic {
+    fn current() -> Self {
+        let apic_base = unsafe { Msr::new(IA32_APIC_BASE).read() };
+        if apic_base & APIC_BASE_X2APIC_ENABLE != 0 {
+            LocalApic::X2Apic
+        } else {
+            LocalApic::XApic((apic_base & APIC_BASE_ADDRESS_MASK) as usize)
+        }
+    }
+
+    fn read(self, register: u32) -> u32 {
+        match self {
+            LocalApic::X2Apic => unsafe {
+                Msr::new(X2APIC_MSR_BASE + (register >> 4)).read() as u32
+            },
+            LocalApic::XApic(base) => unsafe {
+                read_volatile((base + register as usize) as *const u32)
+            },
+        }
+    }
+
+    fn write(self, register: u32, value: u32) {
+        match self {
+            LocalApic::X2Apic => unsafe {
+                Msr::new(X2APIC_MSR_BASE + (register >> 4)).write(value as u64)
+            },
+            LocalApic::XApic(base) => unsafe {
+                write_volatile((base + register as usize) as *mut u32, value)
+            },
+        }
+    }
+
+    /// Whether an interrupt with the given vector is pending, i.e. accepted but not yet delivered.
+    fn is_pending(self, vector: u8) -> bool {
+        let irr = self.read(APIC_IRR + 0x10 * (vector as u32 / 32));
+        irr & (1 << (vector % 32)) != 0
+    }
+}
+
+/// Sets the function to call when the watchdog expires. Only the first call has an effect.
+pub fn init_watchdog(on_timeout: fn() -> !) {
+    TIMEOUT_HANDLER.init_once(|| on_timeout);
+}
+
+/// Arms the watchdog to expire after the given number of milliseconds. Interrupts are enabled
+/// while the watchdog is armed. Arming an armed watchdog restarts it.
+pub fn arm_watchdog(timeout_ms: u64) {
+    disarm_watchdog();
+    let interrupts_enabled = interrupts::are_enabled();
+    interrupts::disable();
+
+    let state = save_state(interrupts_enabled);
+    let apic = LocalApic::current();
+    let owns_spurious_vector = state.spurious & SPURIOUS_APIC_ENABLE == 0;
+    if owns_spurious_vector {
+        apic.write(APIC_SPURIOUS, SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+    OWNS_SPURIOUS_VECTOR.store(owns_spurious_vector, Ordering::SeqCst);
+    let ticks_per_ms = ticks_per_ms(apic);
+
+    super::idt::acquire(super::idt::User::Watchdog);
+
+    REMAINING_MS.store(timeout_ms.max(1), Ordering::SeqCst);
+    ARMED.store(true, Ordering::SeqCst);
+    apic.write(APIC_DIVIDE_CONFIG, DIVIDE_BY_16);
+    apic.write(APIC_LVT_TIMER, LVT_TIMER_PERIODIC | TIMER_VECTOR as u32);
+    apic.write(APIC_INITIAL_COUNT, ticks_per_ms);
+
+    interrupts::enable();
+}
+
+/// Disarms the watchdog, if armed. The previous local APIC state and IDT are restored, and
+/// interrupts are enabled again only if they were before the watchdog was armed. If the watchdog
+/// is not armed, this does nothing.
+pub fn disarm_watchdog() {
+    if !ARMED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    interrupts::disable();
+    let apic = LocalApic::current();
+    apic.write(APIC_LVT_TIMER, LVT_MASKED);
+    apic.write(APIC_INITIAL_COUNT, 0);
+
+    // a tick which is already pending must be taken while the harness IDT is still loaded
+    if apic.is_pending(TIMER_VECTOR) {
+        unsafe { core::arch::asm!("sti", "nop", "cli", options(nomem, nostack)) };
+    }
+
+    let interrupts_enabled = restore_state(apic);
+    super::idt::release(super::idt::User::Watchdog);
+    if interrupts_enabled {
+        interrupts::enable();
+    }
+}
+
+/// Whether the watchdog is currently armed.
+pub(super) fn is_armed() -> bool {
+    ARMED.load(Ordering::SeqCst)
+}
+
+/// Sets the handlers for the local APIC interrupts in the harness IDT.
+pub(super) fn set_handlers(idt: &mut InterruptDescriptorTable) {
+    idt[TIMER_VECTOR].set_handler_fn(timer_interrupt_handler);
+    if OWNS_SPURIOUS_VECTOR.load(Ordering::SeqCst) {
+        idt[SPURIOUS_VECTOR].set_handler_fn(spurious_interrupt_handler);
+    }
+}
+
+/// Saves the local APIC state, globally enabling the local APIC if the kernel has not.
+fn save_state(interrupts_enabled: bool) -> SavedState {
+    let mut apic_base_msr = Msr::new(IA32_APIC_BASE);
+    let apic_base = unsafe { apic_base_msr.read() };
+    if apic_base & APIC_BASE_GLOBAL_ENABLE == 0 {
+        unsafe { apic_base_msr.write(apic_base | APIC_BASE_GLOBAL_ENABLE) };
+    }
+
+    let apic = LocalApic::current();
+    let lvt_timer = apic.read(APIC_LVT_TIMER);
+    let state = SavedState {
+        interrupts_enabled,
+        apic_base,
+        spurious: apic.read(APIC_SPURIOUS),
+        lvt_timer,
+        divide_config: apic.read(APIC_DIVIDE_CONFIG),
+        initial_count: apic.read(APIC_INITIAL_COUNT),
+        // the deadline MSR only exists if the timer can be in TSC-deadline mode
+        tsc_deadline: if lvt_timer & LVT_TIMER_MODE_MASK == LVT_TIMER_TSC_DEADLINE {
+            unsafe { Msr::new(IA32_TSC_DEADLINE).read() }
+        } else {
+            0
+        },
+    };
+    *SAVED_STATE.lock() = Some(state);
+    state
+}
+
+/// Restores the local APIC state from before the watchdog was armed, and returns whether
+/// interrupts were enabled then. The timer must be stopped.
+fn restore_state(apic: LocalApic) -> bool {
+    let Some(state) = SAVED_STATE.lock().take() else {
+        return false;
+    };
+
+    apic.write(APIC_DIVIDE_CONFIG, state.divide_config);
+    apic.write(APIC_LVT_TIMER, state.lvt_timer);
+    if state.lvt_timer & LVT_TIMER_MODE_MASK == LVT_TIMER_TSC_DEADLINE {
+        if state.tsc_deadline != 0 {
+            unsafe { Msr::new(IA32_TSC_DEADLINE).write(state.tsc_deadline) };
+        }
+    } else {
+        apic.write(APIC_INITIAL_COUNT, state.initial_count);
+    }
+    apic.write(APIC_SPURIOUS, state.spurious);
+
+    if state.apic_base & APIC_BASE_GLOBAL_ENABLE == 0 {
+        unsafe { Msr::new(IA32_APIC_BASE).write(state.apic_base) };
+    }
+    state.interrupts_enabled
+}
+
+/// Returns the local APIC timer ticks per millisecond with a divider of 16. The rate is measured
+/// against the PIT on the first call, with the timer masked.
+fn ticks_per_ms(apic: LocalApic) -> u32 {
+    let ticks_per_ms = TICKS_PER_MS.load(Ordering::SeqCst);
+    if ticks_per_ms != 0 {
+        return ticks_per_ms;
+    }
+
+    apic.write(APIC_DIVIDE_CONFIG, DIVIDE_BY_16);
+    apic.write(APIC_LVT_TIMER, LVT_MASKED);
+    apic.write(APIC_INITIAL_COUNT, u32::MAX);
+    let rate = super::measure_rate(|| (u32::MAX - apic.read(APIC_CURRENT_COUNT)) as u64);
+    apic.write(APIC_INITIAL_COUNT, 0);
+
+    let ticks_per_ms = (rate / 1000).clamp(1, u32::MAX as u64) as u32;
+    TICKS_PER_MS.store(ticks_per_ms, Ordering::SeqCst);
+    ticks_per_ms
+}
+
+extern "x86-interrupt" fn timer_interrupt_handler(_frame: InterruptStackFrame) {
+    LocalApic::current().write(APIC_EOI, 0);
+
+    if !ARMED.load(Ordering::SeqCst) {
+        return;
+    }
+
+    if REMAINING_MS.fetch_sub(1, Ordering::SeqCst) <= 1 {
+        disarm_watchdog();
+        if let Some(on_timeout) = TIMEOUT_HANDLER.get() {
+            on_timeout();
+        }
+    }
+}
+
+extern "x86-interrupt" fn spurious_interrupt_handler(_frame: InterruptStackFrame) {
+    // spurious local APIC interrupts must not be acknowledged
+}
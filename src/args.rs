@@ -1,5 +1,6 @@
-use crate::MAX_STRING_LENGTH;
+use crate::{KlibConfig, MAX_STRING_LENGTH};
 use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicBool, Ordering};
 use heapless::String;
 
 /// A global variable to hold the test group name (only one test group per binary)
@@ -14,3 +15,100 @@ pub fn set_test_group(name: &str) {
 pub fn get_test_group() -> Option<&'static str> {
     TEST_GROUP.get().map(|s| s.as_str())
 }
+
+/// A global variable to hold the klib configuration, if one was provided
+static KLIB_CONFIG: OnceCell<&'static KlibConfig> = OnceCell::uninit();
+
+/// Sets the klib configuration. This should be called once during test initialization.
+pub fn set_klib_config(config: &'static KlibConfig) {
+    KLIB_CONFIG.get_or_init(|| config);
+}
+
+/// Gets the klib configuration, if set.
+pub fn get_klib_config() -> Option<&'static KlibConfig> {
+    KLIB_CONFIG.get().copied()
+}
+
+/// Prefix for harness arguments, so they can share the kernel command line with other arguments.
+const ARG_PREFIX: &str = "kunit.";
+
+/// The name of the fw_cfg file from which harness arguments are read at boot.
+const FW_CFG_ARGS_FILE: &str = "opt/kunit/args";
+
+/// A global variable to hold the harness arguments (e.g. test filters) passed in from the host
+static TEST_ARGS: OnceCell<String<MAX_STRING_LENGTH>> = OnceCell::uninit();
+
+/// Whether the harness arguments were longer than `MAX_STRING_LENGTH` and had to be cut off
+static TEST_ARGS_TRUNCATED: AtomicBool = AtomicBool::new(false);
+
+/// Sets the harness arguments. Only the first call has an effect, so arguments set explicitly
+/// before the runner starts take precedence over those read from fw_cfg.
+///
+/// Arguments beyond the maximum length are cut off, which is recorded so that the runner can
+/// report it (see [`test_args_truncated`]).
+pub fn set_test_args(args: &str) {
+    TEST_ARGS.get_or_init(|| {
+        let mut value = String::new();
+        for c in args.chars() {
+            if value.push(c).is_err() {
+                TEST_ARGS_TRUNCATED.store(true, Ordering::SeqCst);
+                break;
+            }
+        }
+        value
+    });
+}
+
+/// Loads the harness arguments from the fw_cfg file `opt/kunit/args`, unless they were already
+/// set. If there is no such file, the arguments are left empty.
+pub fn load_test_args() {
+    if TEST_ARGS.get().is_some() {
+        return;
+    }
+
+    let mut buffer = [0u8; MAX_STRING_LENGTH];
+    let size = crate::fw_cfg::read_file(FW_CFG_ARGS_FILE, &mut buffer).unwrap_or(0);
+    let length = size.min(buffer.len());
+    if size > length {
+        TEST_ARGS_TRUNCATED.store(true, Ordering::SeqCst);
+    }
+    // the cut may have split a character, so keep what was valid before it
+    let args = match core::str::from_utf8(&buffer[..length]) {
+        Ok(args) => args,
+        Err(error) => core::str::from_utf8(&buffer[..error.valid_up_to()]).unwrap_or(""),
+    };
+    set_test_args(args);
+}
+
+/// Whether the harness arguments were cut off at the maximum length (`MAX_STRING_LENGTH`
+/// bytes). The runner refuses to run in that case, since a cut-off filter would select other
+/// tests than the ones asked for.
+pub fn test_args_truncated() -> bool {
+    TEST_ARGS_TRUNCATED.load(Ordering::SeqCst)
+}
+
+/// Iterates over the harness arguments as `(key, value)` pairs. Arguments take the form
+/// `kunit.key=value` or `kunit.key` (with an empty value), separated by whitespace. Anything
+/// without the `kunit.` prefix is skipped.
+pub fn test_args() -> impl Iterator<Item = (&'static str, &'static str)> {
+    TEST_ARGS
+        .get()
+        .map(|s| s.as_str())
+        .unwrap_or("")
+        .split_ascii_whitespace()
+        .filter_map(|arg| arg.strip_prefix(ARG_PREFIX))
+        .map(|arg| arg.split_once('=').unwrap_or((arg, "")))
+}
+
+/// Whether the harness argument `kunit.<key>` is present.
+pub fn has_test_arg(key: &str) -> bool {
+    test_args().any(|(k, _)| k == key)
+}
+
+/// Gets the value of the last harness argument `kunit.<key>=<value>`, if present.
+pub fn get_test_arg(key: &str) -> Option<&'static str> {
+    test_args()
+        .filter(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+        .last()
+}
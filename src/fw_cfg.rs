@@ -0,0 +1,68 @@
+//! A minimal reader for QEMU's firmware configuration device (`fw_cfg`).
+//!
+//! This allows the host to pass data to the harness at boot, for example with
+//! `-fw_cfg name=opt/kunit/args,string=...`. The architecture-specific code only provides the
+//! selector and data register access; the directory format is the same on every architecture.
+
+use crate::arch::{fw_cfg_read_byte, fw_cfg_select};
+
+/// Selector for the `"QEMU"` signature.
+const FW_CFG_SIGNATURE: u16 = 0x0000;
+/// Selector for the file directory.
+const FW_CFG_FILE_DIR: u16 = 0x0019;
+/// Length of a file name in a directory entry, including the NUL terminator.
+const FW_CFG_FILE_NAME_LENGTH: usize = 56;
+
+/// Reads the fw_cfg file with the given name into `buffer`, returning the size of the file.
+///
+/// Returns `None` if there is no fw_cfg device or no file with that name. Files larger than the
+/// buffer are cut off at the buffer length, which callers can tell from the returned size.
+pub fn read_file(name: &str, buffer: &mut [u8]) -> Option<usize> {
+    fw_cfg_select(FW_CFG_SIGNATURE);
+    let mut signature = [0u8; 4];
+    read_bytes(&mut signature);
+    if &signature != b"QEMU" {
+        return None;
+    }
+
+    fw_cfg_select(FW_CFG_FILE_DIR);
+    let count = read_u32_be();
+    for _ in 0..count {
+        let size = read_u32_be() as usize;
+        let selector = read_u16_be();
+        let _reserved = read_u16_be();
+        let mut file_name = [0u8; FW_CFG_FILE_NAME_LENGTH];
+        read_bytes(&mut file_name);
+
+        let file_name_length = file_name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(FW_CFG_FILE_NAME_LENGTH);
+        if &file_name[..file_name_length] == name.as_bytes() {
+            let length = size.min(buffer.len());
+            fw_cfg_select(selector);
+            read_bytes(&mut buffer[..length]);
+            return Some(size);
+        }
+    }
+
+    None
+}
+
+fn read_bytes(buffer: &mut [u8]) {
+    for byte in buffer {
+        *byte = fw_cfg_read_byte();
+    }
+}
+
+fn read_u16_be() -> u16 {
+    let mut bytes = [0u8; 2];
+    read_bytes(&mut bytes);
+    u16::from_be_bytes(bytes)
+}
+
+fn read_u32_be() -> u32 {
+    let mut bytes = [0u8; 4];
+    read_bytes(&mut bytes);
+    u32::from_be_bytes(bytes)
+}
@@ -0,0 +1,193 @@
+//! A minimal streaming JSON writer for `no_std` environments.
+//!
+//! Values are written directly to the underlying [`Write`] implementation as they are emitted,
+//! so no intermediate buffer is needed regardless of how long a string value is. All strings are
+//! escaped according to RFC 8259, which guarantees that every emitted document is valid JSON.
+
+use core::fmt::{self, Write};
+
+/// A streaming JSON writer which emits objects, arrays, strings and numbers to the given output.
+///
+/// The writer keeps track of whether a separator is needed between values, but otherwise trusts
+/// the caller to emit a well-formed sequence of calls (e.g. a `key` before every object value).
+pub struct JsonWriter<W: Write> {
+    out: W,
+    /// Whether the next value is the first in the current object or array.
+    first: bool,
+    /// Whether a key has just been written, meaning the next value must not be preceded by a comma.
+    after_key: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Creates a new JSON writer which writes to the given output.
+    pub const fn new(out: W) -> Self {
+        JsonWriter {
+            out,
+            first: true,
+            after_key: false,
+        }
+    }
+
+    /// Begins a JSON object (`{`).
+    pub fn begin_object(&mut self) -> fmt::Result {
+        self.separate()?;
+        self.first = true;
+        self.out.write_char('{')
+    }
+
+    /// Ends the current JSON object (`}`).
+    pub fn end_object(&mut self) -> fmt::Result {
+        self.first = false;
+        self.out.write_char('}')
+    }
+
+    /// Begins a JSON array (`[`).
+    pub fn begin_array(&mut self) -> fmt::Result {
+        self.separate()?;
+        self.first = true;
+        self.out.write_char('[')
+    }
+
+    /// Ends the current JSON array (`]`).
+    pub fn end_array(&mut self) -> fmt::Result {
+        self.first = false;
+        self.out.write_char(']')
+    }
+
+    /// Writes an object key. The next call must write the corresponding value.
+    pub fn key(&mut self, key: &str) -> fmt::Result {
+        self.separate()?;
+        write_escaped(&mut self.out, key)?;
+        self.out.write_char(':')?;
+        self.after_key = true;
+        Ok(())
+    }
+
+    /// Writes a string value, escaping it as needed.
+    pub fn string(&mut self, value: &str) -> fmt::Result {
+        self.separate()?;
+        write_escaped(&mut self.out, value)
+    }
+
+    /// Writes a string value from format arguments, escaping the formatted output as it is
+    /// produced. This avoids formatting into a fixed-size buffer first.
+    pub fn string_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.separate()?;
+        self.out.write_char('"')?;
+        EscapedWriter(&mut self.out).write_fmt(args)?;
+        self.out.write_char('"')
+    }
+
+    /// Writes a non-negative fixed-point number, `value / 10^decimals`, with all decimal places
+    /// (e.g. a duration in nanoseconds as seconds, with 9 decimal places).
+    pub fn fixed_point(&mut self, value: u64, decimals: u32) -> fmt::Result {
+        self.separate()?;
+        let scale = 10u64.pow(decimals);
+        write!(
+            self.out,
+            "{}.{:0width$}",
+            value / scale,
+            value % scale,
+            width = decimals as usize
+        )
+    }
+
+    /// Writes an unsigned integer value.
+    pub fn u64(&mut self, value: u64) -> fmt::Result {
+        self.separate()?;
+        write!(self.out, "{}", value)
+    }
+
+    /// Writes a signed integer value.
+    pub fn i64(&mut self, value: i64) -> fmt::Result {
+        self.separate()?;
+        write!(self.out, "{}", value)
+    }
+
+    /// Writes a boolean value.
+    pub fn bool(&mut self, value: bool) -> fmt::Result {
+        self.separate()?;
+        self.out.write_str(if value { "true" } else { "false" })
+    }
+
+    /// Writes a `null` value.
+    pub fn null(&mut self) -> fmt::Result {
+        self.separate()?;
+        self.out.write_str("null")
+    }
+
+    /// Writes a key and string value pair.
+    pub fn field_str(&mut self, key: &str, value: &str) -> fmt::Result {
+        self.key(key)?;
+        self.string(value)
+    }
+
+    /// Writes a key and unsigned integer value pair.
+    pub fn field_u64(&mut self, key: &str, value: u64) -> fmt::Result {
+        self.key(key)?;
+        self.u64(value)
+    }
+
+    /// Writes a key and boolean value pair.
+    pub fn field_bool(&mut self, key: &str, value: bool) -> fmt::Result {
+        self.key(key)?;
+        self.bool(value)
+    }
+
+    /// Ends the current line, for line-delimited JSON output. The writer is reset so that the
+    /// next value starts a new document.
+    pub fn end_line(&mut self) -> fmt::Result {
+        self.first = true;
+        self.after_key = false;
+        self.out.write_char('\n')
+    }
+
+    /// Writes a comma if the next value is not the first in its container.
+    fn separate(&mut self) -> fmt::Result {
+        if self.after_key {
+            self.after_key = false;
+        } else if !self.first {
+            self.out.write_char(',')?;
+        }
+        self.first = false;
+        Ok(())
+    }
+}
+
+/// Writes the given string as a quoted and escaped JSON string.
+fn write_escaped<W: Write>(out: &mut W, value: &str) -> fmt::Result {
+    out.write_char('"')?;
+    EscapedWriter(&mut *out).write_str(value)?;
+    out.write_char('"')
+}
+
+/// A [`Write`] adapter which escapes everything written through it for use inside a JSON string.
+struct EscapedWriter<'a, W: Write>(&'a mut W);
+
+impl<W: Write> Write for EscapedWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // write unescaped runs in one go, only breaking them up where an escape is needed
+        let mut run_start = 0;
+        for (i, c) in s.char_indices() {
+            let escape = match c {
+                '"' => Some("\\\""),
+                '\\' => Some("\\\\"),
+                '\n' => Some("\\n"),
+                '\r' => Some("\\r"),
+                '\t' => Some("\\t"),
+                '\u{08}' => Some("\\b"),
+                '\u{0c}' => Some("\\f"),
+                c if (c as u32) < 0x20 => None, // other control characters use \u escapes
+                _ => continue,
+            };
+
+            self.0.write_str(&s[run_start..i])?;
+            match escape {
+                Some(escape) => self.0.write_str(escape)?,
+                None => write!(self.0, "\\u{:04x}", c as u32)?,
+            }
+            run_start = i + c.len_utf8();
+        }
+        self.0.write_str(&s[run_start..])
+    }
+}
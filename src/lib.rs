@@ -1,4 +1,5 @@
 #![no_std]
+#![cfg_attr(target_arch = "x86_64", feature(abi_x86_interrupt))]
 #![cfg_attr(test, no_main)]
 #![cfg_attr(test, feature(custom_test_frameworks))]
 #![cfg_attr(test, test_runner(runner))]
@@ -9,14 +10,22 @@ extern crate self as kunit;
 
 mod arch;
 mod args;
+mod fw_cfg;
+pub mod json;
 pub mod macros;
 mod print;
 mod qemu;
+pub mod sink;
 pub mod test;
+#[cfg(target_arch = "aarch64")]
+pub mod watchdog;
 
 pub use kunit_macros::kunit;
 pub use macros::klib::{KlibConfig, KlibConfigBuilder};
-pub use test::{runner::runner, split_module_path, split_module_path_len, Test};
+pub use test::{
+    output::OutputFormat, runner::runner, split_module_path, split_module_path_len, Test,
+    TestWithOptions,
+};
 
 /// Maximum length for strings used in this library, to avoid dynamic allocations.
 const MAX_STRING_LENGTH: usize = 1024;
@@ -37,6 +46,35 @@ pub fn init_harness(test_group: &str) {
     args::set_test_group(test_group);
 }
 
+/// Initialize the test harness with the given test group and configuration. This is the same
+/// as [`init_harness`], but also makes the configuration (such as test timeouts) available to
+/// the test runner, and prepares the configured output sink.
+///
+/// If you are using the `klib!` macro, this function is called automatically.
+pub fn init_harness_with_config(test_group: &str, config: &'static KlibConfig) {
+    args::set_test_group(test_group);
+    args::set_klib_config(config);
+    config.output_sink.init();
+}
+
+/// Set the harness arguments, such as test filters, for this run. This should be called before
+/// the main test function is called, e.g. with arguments taken from the kernel command line.
+///
+/// Arguments take the form `kunit.key=value` or `kunit.key`, separated by whitespace; anything
+/// without the `kunit.` prefix is ignored, so a whole kernel command line can be passed as-is.
+/// If this function is not called, the arguments are read from the QEMU fw_cfg file
+/// `opt/kunit/args` instead (e.g. `-fw_cfg name=opt/kunit/args,string=kunit.filter=alloc`).
+///
+/// Supported arguments:
+/// - `kunit.filter=<pattern>`: only run tests whose qualified name matches the pattern
+/// - `kunit.skip=<pattern>`: do not run tests whose qualified name matches the pattern
+/// - `kunit.exact`: plain patterns must match the whole qualified name, not a substring
+///
+/// Patterns containing `*` or `?` are matched as globs against the whole qualified name.
+pub fn set_test_args(args: &str) {
+    args::set_test_args(args);
+}
+
 /// Disable CPU interrupts for deterministic test execution.
 pub fn disable_interrupts() {
     arch::disable_interrupts();
@@ -59,26 +97,26 @@ pub fn disable_interrupts() {
 ///
 /// If you are using the `klib!` macro, this function is included automatically.
 pub fn panic(info: &core::panic::PanicInfo) -> ! {
-    use crate::test::runner::TestRunner;
     use crate::test::runner::TEST_RUNNER;
 
     if let Some(runner) = TEST_RUNNER.get() {
         runner.handle_panic(info);
     }
 
-    use heapless::{format, String};
-
     let test_group = args::get_test_group().unwrap_or("default");
-    test::output::write_test_group(test_group, 0);
-
-    let location: String<MAX_STRING_LENGTH> = if let Some(location) = info.location() {
-        format!("{}:{}", location.file(), location.line()).unwrap()
-    } else {
-        String::<MAX_STRING_LENGTH>::try_from("unknown location").unwrap()
-    };
-    let message = info.message().as_str().unwrap_or("no message");
-    let test_name: String<MAX_STRING_LENGTH> = format!("bootstrap::panic").unwrap();
+    test::output::write_event(&test::output::Event::Group {
+        test_group,
+        test_count: 0,
+        selected_count: 0,
+        shuffle_seed: None,
+    });
 
-    test::output::write_test_failure(&test_name, location.as_str(), message);
-    qemu::exit(qemu::ExitCode::_Failed)
+    let details = test::panic::PanicDetails::from_info(info);
+    test::output::write_event(&test::output::Event::Fail {
+        test: "bootstrap::panic",
+        cycle_count: 0,
+        location: details.location.as_str(),
+        message: details.message.as_str(),
+    });
+    qemu::exit(qemu::ExitCode::Failed)
 }
@@ -33,7 +33,7 @@ macro_rules! klib {
             #[unsafe(link_section = ".text._start")]
             pub extern "C" fn _start() -> ! {
                 kunit::disable_interrupts();
-                kunit::init_harness($test_group);
+                kunit::init_harness_with_config($test_group, &___KLIB_CONFIG);
 
                 if let Some(before_tests) = ___KLIB_CONFIG.before_tests {
                     before_tests();
@@ -67,9 +67,50 @@ macro_rules! klib {
     };
 }
 
+use crate::sink::{DebugCon, OutputSink};
+use crate::test::output::OutputFormat;
+use crate::test::runner::TestRunner;
+#[cfg(target_arch = "aarch64")]
+use crate::watchdog::GicV2;
+
 pub struct KlibConfig {
     pub before_tests: Option<fn()>,
     pub after_tests: Option<fn()>,
+    /// Runs before each test which is run (not ignored or filtered out).
+    pub before_each: Option<fn()>,
+    /// Runs after each test which is run, even if the test panics or times out. If this panics
+    /// while cleaning up after a panicked test, the run ends with a harness error.
+    pub after_each: Option<fn()>,
+    /// Runs whenever the runner enters a module to run one of its tests, with the module path.
+    /// Ignored and filtered-out tests do not enter their module. The fixtures run per visit, not
+    /// per module: in shuffled order, the tests of a module are not consecutive, so the module
+    /// is entered (and left) again for every run of consecutive tests from it. A panic in it
+    /// fails the test which entered the module, and the module is not entered, so
+    /// `after_module` does not run for it.
+    pub before_module: Option<fn(&'static str)>,
+    /// Runs whenever the runner leaves a module which it entered (including after the last
+    /// test), with the module path.
+    pub after_module: Option<fn(&'static str)>,
+    /// The default timeout for each test, in milliseconds. Tests which run longer are stopped by
+    /// a timer interrupt and reported with a `"timeout"` result. Individual tests may override
+    /// this. `None` disables timeouts (the default).
+    pub test_timeout_ms: Option<u64>,
+    /// The format of the test output (JSONL by default).
+    pub output_format: OutputFormat,
+    /// The device to which the test output is written (the debug console by default). See
+    /// [`crate::sink`] for the built-in sinks.
+    pub output_sink: &'static dyn OutputSink,
+    /// The test runner to use instead of `KernelTestRunner`, for custom scheduling, reporting or
+    /// recovery policies. `None` uses `KernelTestRunner` (the default).
+    pub test_runner: Option<&'static dyn TestRunner>,
+    /// Whether CPU exceptions (page faults, invalid opcodes, aborts, ...) raised by a test are
+    /// caught and reported as a failure of that test, instead of ending the run. The harness
+    /// installs its own IDT or vector table while each test runs.
+    pub catch_exceptions: bool,
+    /// The GICv2 through which the watchdog takes its timer interrupt (the QEMU `virt` machine's
+    /// by default). See [`crate::watchdog`].
+    #[cfg(target_arch = "aarch64")]
+    pub gic: GicV2,
 }
 
 impl KlibConfig {
@@ -77,6 +118,17 @@ impl KlibConfig {
         KlibConfig {
             before_tests: None,
             after_tests: None,
+            before_each: None,
+            after_each: None,
+            before_module: None,
+            after_module: None,
+            test_timeout_ms: None,
+            output_format: OutputFormat::Json,
+            output_sink: &DebugCon,
+            test_runner: None,
+            catch_exceptions: false,
+            #[cfg(target_arch = "aarch64")]
+            gic: GicV2::QEMU_VIRT,
         }
     }
 }
@@ -84,6 +136,17 @@ impl KlibConfig {
 pub struct KlibConfigBuilder {
     pub before_tests: Option<fn()>,
     pub after_tests: Option<fn()>,
+    pub before_each: Option<fn()>,
+    pub after_each: Option<fn()>,
+    pub before_module: Option<fn(&'static str)>,
+    pub after_module: Option<fn(&'static str)>,
+    pub test_timeout_ms: Option<u64>,
+    pub output_format: OutputFormat,
+    pub output_sink: &'static dyn OutputSink,
+    pub test_runner: Option<&'static dyn TestRunner>,
+    pub catch_exceptions: bool,
+    #[cfg(target_arch = "aarch64")]
+    pub gic: GicV2,
 }
 
 impl KlibConfigBuilder {
@@ -91,6 +154,17 @@ impl KlibConfigBuilder {
         KlibConfigBuilder {
             before_tests: None,
             after_tests: None,
+            before_each: None,
+            after_each: None,
+            before_module: None,
+            after_module: None,
+            test_timeout_ms: None,
+            output_format: OutputFormat::Json,
+            output_sink: &DebugCon,
+            test_runner: None,
+            catch_exceptions: false,
+            #[cfg(target_arch = "aarch64")]
+            gic: GicV2::QEMU_VIRT,
         }
     }
 
@@ -98,6 +172,17 @@ impl KlibConfigBuilder {
         KlibConfigBuilder {
             before_tests,
             after_tests,
+            before_each: None,
+            after_each: None,
+            before_module: None,
+            after_module: None,
+            test_timeout_ms: None,
+            output_format: OutputFormat::Json,
+            output_sink: &DebugCon,
+            test_runner: None,
+            catch_exceptions: false,
+            #[cfg(target_arch = "aarch64")]
+            gic: GicV2::QEMU_VIRT,
         }
     }
 
@@ -105,6 +190,17 @@ impl KlibConfigBuilder {
         KlibConfig {
             before_tests: self.before_tests,
             after_tests: self.after_tests,
+            before_each: self.before_each,
+            after_each: self.after_each,
+            before_module: self.before_module,
+            after_module: self.after_module,
+            test_timeout_ms: self.test_timeout_ms,
+            output_format: self.output_format,
+            output_sink: self.output_sink,
+            test_runner: self.test_runner,
+            catch_exceptions: self.catch_exceptions,
+            #[cfg(target_arch = "aarch64")]
+            gic: self.gic,
         }
     }
 
@@ -117,4 +213,55 @@ impl KlibConfigBuilder {
         self.after_tests = Some(after_tests);
         self
     }
+
+    pub const fn before_each(mut self, before_each: fn()) -> Self {
+        self.before_each = Some(before_each);
+        self
+    }
+
+    pub const fn after_each(mut self, after_each: fn()) -> Self {
+        self.after_each = Some(after_each);
+        self
+    }
+
+    pub const fn before_module(mut self, before_module: fn(&'static str)) -> Self {
+        self.before_module = Some(before_module);
+        self
+    }
+
+    pub const fn after_module(mut self, after_module: fn(&'static str)) -> Self {
+        self.after_module = Some(after_module);
+        self
+    }
+
+    pub const fn test_timeout_ms(mut self, test_timeout_ms: u64) -> Self {
+        self.test_timeout_ms = Some(test_timeout_ms);
+        self
+    }
+
+    pub const fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    pub const fn output_sink(mut self, output_sink: &'static dyn OutputSink) -> Self {
+        self.output_sink = output_sink;
+        self
+    }
+
+    pub const fn test_runner(mut self, test_runner: &'static dyn TestRunner) -> Self {
+        self.test_runner = Some(test_runner);
+        self
+    }
+
+    pub const fn catch_exceptions(mut self, catch_exceptions: bool) -> Self {
+        self.catch_exceptions = catch_exceptions;
+        self
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub const fn gic(mut self, gic: GicV2) -> Self {
+        self.gic = gic;
+        self
+    }
 }
@@ -1,7 +1,5 @@
-use crate::MAX_STRING_LENGTH;
 #[cfg(target_arch = "x86_64")]
 use conquer_once::spin::OnceCell;
-use heapless::String;
 #[cfg(target_arch = "x86_64")]
 use spin::Mutex;
 
@@ -10,15 +8,25 @@ use spin::Mutex;
 #[allow(dead_code)]
 pub static SERIAL1: OnceCell<Mutex<uart_16550::SerialPort>> = OnceCell::uninit();
 
+/// The I/O port base of the global serial port (COM1)
+#[cfg(target_arch = "x86_64")]
+pub(crate) const SERIAL1_PORT: u16 = 0x3F8;
+
 /// Initialize the global serial port
 #[cfg(target_arch = "x86_64")]
 #[allow(dead_code)]
 fn init_serial() -> Mutex<uart_16550::SerialPort> {
-    let mut serial_port = unsafe { uart_16550::SerialPort::new(0x3F8) };
+    let mut serial_port = unsafe { uart_16550::SerialPort::new(SERIAL1_PORT) };
     serial_port.init();
     Mutex::new(serial_port)
 }
 
+/// The global serial port, initialized on first use
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn serial1() -> &'static Mutex<uart_16550::SerialPort> {
+    SERIAL1.get_or_init(|| init_serial())
+}
+
 /// Print to the global serial port
 #[doc(hidden)]
 pub fn _serial_print(args: core::fmt::Arguments) {
@@ -28,8 +36,7 @@ pub fn _serial_print(args: core::fmt::Arguments) {
         use x86_64::instructions::interrupts;
 
         interrupts::without_interrupts(|| {
-            let serial = SERIAL1.get_or_init(|| init_serial());
-            serial
+            serial1()
                 .lock()
                 .write_fmt(args)
                 .expect("Printing to serial failed");
@@ -59,15 +66,24 @@ macro_rules! serial_println {
     };
 }
 
+/// A writer for an output sink, which passes everything written straight to the sink without
+/// any intermediate buffering.
+pub struct SinkWriter(pub &'static dyn crate::sink::OutputSink);
+
+impl core::fmt::Write for SinkWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
 /// Print to the debug console (macro helper)
 #[doc(hidden)]
 pub fn _debugcon_print(args: core::fmt::Arguments) {
-    // convert args to string
     use core::fmt::Write;
-    let mut s = String::<MAX_STRING_LENGTH>::new();
-    s.write_fmt(args).expect("Failed to write to string");
-
-    crate::arch::debug_write(s.as_bytes());
+    SinkWriter(&crate::sink::DebugCon)
+        .write_fmt(args)
+        .expect("Printing to debug console failed");
 }
 
 /// Print to the debug console
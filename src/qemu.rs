@@ -3,11 +3,19 @@ pub fn exit(exit_code: ExitCode) -> ! {
     crate::arch::exit(exit_code as u32)
 }
 
-/// Exit codes for QEMU. These codes are written to the I/O port `0xf4`
+/// Exit codes for QEMU. On x86_64 these codes are written to the I/O port `0xf4`
 /// to signal QEMU to exit with the given code.
+///
+/// QEMU's `isa-debug-exit` device turns a written value `v` into the process exit status
+/// `(v << 1) | 1`, so these map to exit statuses 33, 35 and 37 respectively. On aarch64 the same
+/// statuses are passed to the semihosting `SYS_EXIT_EXTENDED` call.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum ExitCode {
+    /// All tests passed (or were ignored).
     Success = 0x10,
-    _Failed = 0x11,
+    /// At least one test failed.
+    Failed = 0x11,
+    /// The test harness itself failed, so the results cannot be trusted.
+    HarnessError = 0x12,
 }
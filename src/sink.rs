@@ -0,0 +1,320 @@
+//! Output sinks, the devices to which the runner writes its test output.
+//!
+//! By default, output goes to the debug console (port 0xE9 on x86_64, the PL011 UART of the QEMU
+//! `virt` machine on aarch64). Kernels which run without a debug console, or which already own
+//! that device, can select another sink with `KlibConfigBuilder::output_sink`:
+//!
+//! ```
+//! kunit::klib!("kernel", klib_config = &kunit::KlibConfigBuilder::new_default()
+//!     .output_sink(&kunit::sink::Uart16550::COM2)
+//!     .build());
+//! ```
+//!
+//! MMIO sinks access their registers through the physical address, so the kernel must have that
+//! address identity mapped while the tests run.
+
+use core::ptr::{read_volatile, write_volatile};
+use spin::Mutex;
+
+/// A device to which test output is written.
+///
+/// Implementations must not panic, since output is also written while a panic is handled.
+pub trait OutputSink: Sync {
+    /// Prepares the device for output. This is called once, when the harness is initialized.
+    fn init(&self) {}
+
+    /// Writes the given bytes to the device, blocking until they have been accepted.
+    fn write(&self, bytes: &[u8]);
+}
+
+/// The architecture's debug console (the default sink).
+pub struct DebugCon;
+
+impl OutputSink for DebugCon {
+    fn write(&self, bytes: &[u8]) {
+        crate::arch::debug_write(bytes);
+    }
+}
+
+/// A 16550-compatible serial port, accessed through port I/O with the `uart_16550` crate. The
+/// port is programmed by `SerialPort::init` when the harness is initialized.
+///
+/// COM1 is also the port of `serial_print!`, so the sink for it shares that port (and its lock)
+/// instead of programming the port again, and output from both is not interleaved. The lock is
+/// taken with interrupts disabled, as for [`RingBuffer`].
+#[cfg(target_arch = "x86_64")]
+pub struct Uart16550 {
+    base: u16,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Uart16550 {
+    /// The first serial port, shared with `serial_print!`.
+    pub const COM1: Uart16550 = Uart16550::new(crate::print::SERIAL1_PORT);
+    /// The second serial port, for kernels which already use COM1 themselves.
+    pub const COM2: Uart16550 = Uart16550::new(0x2f8);
+
+    /// Creates a sink for the 16550 serial port at the given I/O port base.
+    pub const fn new(base: u16) -> Self {
+        Uart16550 { base }
+    }
+
+    /// Whether this is the port of `serial_print!`.
+    fn is_serial1(&self) -> bool {
+        self.base == crate::print::SERIAL1_PORT
+    }
+
+    /// The serial port. `SerialPort` only holds the port base, so it is created for each access
+    /// rather than kept behind a lock, which would rule out writing from interrupt handlers.
+    fn port(&self) -> uart_16550::SerialPort {
+        unsafe { uart_16550::SerialPort::new(self.base) }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl OutputSink for Uart16550 {
+    fn init(&self) {
+        if self.is_serial1() {
+            crate::print::serial1();
+        } else {
+            self.port().init();
+        }
+    }
+
+    fn write(&self, bytes: &[u8]) {
+        fn send(port: &mut uart_16550::SerialPort, bytes: &[u8]) {
+            for &byte in bytes {
+                // unlike `send`, this does not turn backspace and delete into an erase sequence
+                port.send_raw(byte);
+            }
+        }
+
+        if !self.is_serial1() {
+            send(&mut self.port(), bytes);
+            return;
+        }
+
+        crate::arch::without_interrupts(|| match crate::print::serial1().try_lock() {
+            Some(mut port) => send(&mut port, bytes),
+            // with interrupts disabled, the lock can only be held by a `serial_print!` which
+            // panicked and will never release it, so the port is written without it
+            None => send(&mut self.port(), bytes),
+        });
+    }
+}
+
+/// An ARM PL011 UART, accessed through MMIO. The UART is expected to be set up by the firmware,
+/// as it is on QEMU.
+pub struct Pl011 {
+    base: usize,
+}
+
+impl Pl011 {
+    /// The UART of the QEMU `virt` machine, which is also the aarch64 debug console.
+    pub const QEMU_VIRT: Pl011 = Pl011::new(0x0900_0000);
+
+    const DR: usize = 0x00;
+    const FR: usize = 0x18;
+    const FR_TXFF: u32 = 1 << 5;
+
+    /// Creates a sink for the PL011 UART at the given physical address.
+    pub const fn new(base: usize) -> Self {
+        Pl011 { base }
+    }
+}
+
+impl OutputSink for Pl011 {
+    fn write(&self, bytes: &[u8]) {
+        let data = (self.base + Self::DR) as *mut u32;
+        let flags = (self.base + Self::FR) as *const u32;
+
+        for &byte in bytes {
+            unsafe {
+                // wait while the transmit FIFO is full
+                while read_volatile(flags) & Self::FR_TXFF != 0 {
+                    core::hint::spin_loop();
+                }
+                write_volatile(data, byte as u32);
+            }
+        }
+    }
+}
+
+/// A virtio console on the virtio-mmio transport (e.g. `-device virtio-serial-device` on the
+/// QEMU `virt` machine), written through the emergency write register.
+///
+/// Emergency writes need no virtqueues, so the device only has to be discovered, not fully
+/// driven. Output goes to the console's first port. The device must offer the
+/// `VIRTIO_CONSOLE_F_EMERG_WRITE` feature, which QEMU does by default. If there is no virtio
+/// console at the given address, nothing is written.
+pub struct VirtioConsole {
+    base: usize,
+}
+
+impl VirtioConsole {
+    const MAGIC_VALUE: usize = 0x000;
+    const DEVICE_ID: usize = 0x008;
+    const DEVICE_FEATURES: usize = 0x010;
+    const DEVICE_FEATURES_SEL: usize = 0x014;
+    const DRIVER_FEATURES: usize = 0x020;
+    const DRIVER_FEATURES_SEL: usize = 0x024;
+    const STATUS: usize = 0x070;
+    /// The `emerg_wr` field of the console configuration.
+    const EMERG_WR: usize = 0x100 + 8;
+
+    /// `"virt"` in little endian.
+    const MAGIC: u32 = 0x7472_6976;
+    const CONSOLE_DEVICE_ID: u32 = 3;
+    const F_EMERG_WRITE: u32 = 1 << 2;
+    /// `VIRTIO_F_VERSION_1`, bit 32 (bit 0 of the second feature word).
+    const F_VERSION_1: u32 = 1 << 0;
+
+    const STATUS_ACKNOWLEDGE: u32 = 1;
+    const STATUS_DRIVER: u32 = 2;
+    const STATUS_DRIVER_OK: u32 = 4;
+    const STATUS_FEATURES_OK: u32 = 8;
+
+    /// Creates a sink for the virtio-mmio device at the given physical address.
+    pub const fn new(base: usize) -> Self {
+        VirtioConsole { base }
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_register(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Whether the device is a virtio console which has accepted emergency writes.
+    fn is_ready(&self) -> bool {
+        self.read(Self::MAGIC_VALUE) == Self::MAGIC
+            && self.read(Self::DEVICE_ID) == Self::CONSOLE_DEVICE_ID
+            && self.read(Self::STATUS) & Self::STATUS_DRIVER_OK != 0
+    }
+}
+
+impl OutputSink for VirtioConsole {
+    fn init(&self) {
+        if self.read(Self::MAGIC_VALUE) != Self::MAGIC
+            || self.read(Self::DEVICE_ID) != Self::CONSOLE_DEVICE_ID
+        {
+            return;
+        }
+
+        // reset, then negotiate only the emergency write feature
+        self.write_register(Self::STATUS, 0);
+        self.write_register(Self::STATUS, Self::STATUS_ACKNOWLEDGE | Self::STATUS_DRIVER);
+
+        self.write_register(Self::DEVICE_FEATURES_SEL, 0);
+        let features = self.read(Self::DEVICE_FEATURES) & Self::F_EMERG_WRITE;
+        self.write_register(Self::DEVICE_FEATURES_SEL, 1);
+        let features_high = self.read(Self::DEVICE_FEATURES) & Self::F_VERSION_1;
+        if features == 0 {
+            self.write_register(Self::STATUS, 0);
+            return;
+        }
+
+        self.write_register(Self::DRIVER_FEATURES_SEL, 0);
+        self.write_register(Self::DRIVER_FEATURES, features);
+        self.write_register(Self::DRIVER_FEATURES_SEL, 1);
+        self.write_register(Self::DRIVER_FEATURES, features_high);
+
+        let status = Self::STATUS_ACKNOWLEDGE | Self::STATUS_DRIVER | Self::STATUS_FEATURES_OK;
+        self.write_register(Self::STATUS, status);
+        if self.read(Self::STATUS) & Self::STATUS_FEATURES_OK == 0 {
+            self.write_register(Self::STATUS, 0);
+            return;
+        }
+        self.write_register(Self::STATUS, status | Self::STATUS_DRIVER_OK);
+    }
+
+    fn write(&self, bytes: &[u8]) {
+        if !self.is_ready() {
+            return;
+        }
+
+        for &byte in bytes {
+            self.write_register(Self::EMERG_WR, byte as u32);
+        }
+    }
+}
+
+/// An in-memory ring buffer, for kernels which forward the test output themselves (or inspect
+/// it from a debugger). Once full, the oldest output is overwritten.
+///
+/// A ring buffer has to be a `static` so that it can be referenced from the `KlibConfig`:
+///
+/// ```
+/// static OUTPUT: kunit::sink::RingBuffer<65536> = kunit::sink::RingBuffer::new();
+/// ```
+///
+/// Output is also written from the timeout and exception handlers, which interrupt the test and
+/// never return to it. Interrupts are therefore disabled while the buffer is locked, so that the
+/// watchdog cannot fire while the lock is held (by a write, or by `with_contents`).
+pub struct RingBuffer<const N: usize> {
+    state: Mutex<RingBufferState<N>>,
+}
+
+struct RingBufferState<const N: usize> {
+    bytes: [u8; N],
+    /// The index at which the next byte is written.
+    next: usize,
+    /// Whether the buffer has wrapped around, so that all `N` bytes are in use.
+    wrapped: bool,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    /// Creates an empty ring buffer.
+    pub const fn new() -> Self {
+        RingBuffer {
+            state: Mutex::new(RingBufferState {
+                bytes: [0; N],
+                next: 0,
+                wrapped: false,
+            }),
+        }
+    }
+
+    /// Calls `f` with the buffered output, oldest first, as two slices (the second one is empty
+    /// unless the buffer has wrapped around).
+    ///
+    /// `f` runs with interrupts disabled.
+    pub fn with_contents<R>(&self, f: impl FnOnce(&[u8], &[u8]) -> R) -> R {
+        crate::arch::without_interrupts(|| {
+            let state = self.state.lock();
+            if state.wrapped {
+                f(&state.bytes[state.next..], &state.bytes[..state.next])
+            } else {
+                f(&state.bytes[..state.next], &[])
+            }
+        })
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> OutputSink for RingBuffer<N> {
+    fn write(&self, bytes: &[u8]) {
+        if N == 0 {
+            return;
+        }
+
+        crate::arch::without_interrupts(|| {
+            let mut state = self.state.lock();
+            for &byte in bytes {
+                let next = state.next;
+                state.bytes[next] = byte;
+                state.next = (next + 1) % N;
+                if state.next == 0 {
+                    state.wrapped = true;
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,96 @@
+use core::fmt;
+use heapless::Vec;
+
+/// The maximum number of registers in an exception's register dump.
+pub const MAX_REGISTERS: usize = 40;
+
+/// An architecture-independent class of CPU exception, which a test can expect to raise with
+/// `ShouldFault`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// A memory access fault: a page fault on x86_64, an instruction or data abort on aarch64.
+    PageFault,
+    /// A general protection fault (x86_64 only).
+    GeneralProtection,
+    /// An invalid or undefined instruction.
+    InvalidOpcode,
+    /// An integer division by zero (x86_64 only, aarch64 does not trap on division).
+    DivideError,
+    /// A breakpoint instruction (`int3` or `brk`).
+    Breakpoint,
+    /// A misaligned access, instruction pointer or stack pointer.
+    Alignment,
+    /// A floating point exception.
+    FloatingPoint,
+    /// Any other exception, such as a double fault or an SError.
+    Other,
+}
+
+impl Fault {
+    /// The name of the fault, as written in the test listing.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Fault::PageFault => "page fault",
+            Fault::GeneralProtection => "general protection fault",
+            Fault::InvalidOpcode => "invalid opcode",
+            Fault::DivideError => "divide error",
+            Fault::Breakpoint => "breakpoint",
+            Fault::Alignment => "alignment fault",
+            Fault::FloatingPoint => "floating point exception",
+            Fault::Other => "other exception",
+        }
+    }
+}
+
+/// The details of a CPU exception (such as a page fault) raised by a test, captured by the
+/// harness exception handlers.
+pub struct ExceptionInfo {
+    /// The class of exception, for matching against `ShouldFault`.
+    pub fault: Fault,
+    /// The type of exception, e.g. `"page fault"` or `"data abort"`.
+    pub kind: &'static str,
+    /// The address of the instruction which raised the exception.
+    pub instruction_pointer: u64,
+    /// The memory address which was accessed, for exceptions which report one (`CR2` on x86_64,
+    /// `FAR_EL1` on aarch64).
+    pub address: Option<u64>,
+    /// The error code pushed by the CPU on x86_64, or the exception syndrome (`ESR_EL1`) on
+    /// aarch64.
+    pub error_code: Option<u64>,
+    /// The register values at the time of the exception, by name.
+    pub registers: Vec<(&'static str, u64), MAX_REGISTERS>,
+}
+
+impl ExceptionInfo {
+    /// Creates the details of an exception without any registers.
+    pub fn new(fault: Fault, kind: &'static str, instruction_pointer: u64) -> Self {
+        ExceptionInfo {
+            fault,
+            kind,
+            instruction_pointer,
+            address: None,
+            error_code: None,
+            registers: Vec::new(),
+        }
+    }
+
+    /// Adds a register to the register dump. Registers beyond [`MAX_REGISTERS`] are left out.
+    pub fn push_register(&mut self, name: &'static str, value: u64) {
+        let _ = self.registers.push((name, value));
+    }
+}
+
+/// Formats a one-line summary of the exception, e.g.
+/// `page fault at address 0xdead000 (error code 0x2)`.
+impl fmt::Display for ExceptionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.kind)?;
+        if let Some(address) = self.address {
+            write!(f, " at address {address:#x}")?;
+        }
+        if let Some(error_code) = self.error_code {
+            write!(f, " (error code {error_code:#x})")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use crate::args;
+
+/// Decides which tests run, based on the `kunit.filter`, `kunit.skip` and `kunit.exact` harness
+/// arguments.
+///
+/// - `kunit.filter=<pattern>` runs only tests matching the pattern (may be repeated)
+/// - `kunit.skip=<pattern>` does not run tests matching the pattern (may be repeated)
+/// - `kunit.exact` makes plain patterns match the whole qualified name instead of a substring
+///
+/// Patterns containing `*` or `?` are globs and always match the whole qualified name
+/// (`modules::name`). When no filter is given, every test which is not skipped runs.
+pub fn is_included(qualified_name: &str) -> bool {
+    let exact = args::has_test_arg("exact");
+
+    let mut has_filter = false;
+    let mut filtered_in = false;
+    for (key, pattern) in args::test_args() {
+        match key {
+            "filter" => {
+                has_filter = true;
+                filtered_in |= pattern_matches(pattern, qualified_name, exact);
+            }
+            "skip" if pattern_matches(pattern, qualified_name, exact) => {
+                return false;
+            }
+            _ => {}
+        }
+    }
+
+    !has_filter || filtered_in
+}
+
+/// Whether the pattern matches the qualified test name.
+fn pattern_matches(pattern: &str, qualified_name: &str, exact: bool) -> bool {
+    if pattern.contains(['*', '?']) {
+        glob_matches(pattern.as_bytes(), qualified_name.as_bytes())
+    } else if exact {
+        pattern == qualified_name
+    } else {
+        qualified_name.contains(pattern)
+    }
+}
+
+/// Matches a glob pattern, where `*` matches any sequence and `?` matches any single byte.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    // position of the last `*` in the pattern, and where in the text it started matching
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // let the last `*` swallow one more byte and try again
+            backtrack = Some((star, matched + 1));
+            p = star + 1;
+            t = matched + 1;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
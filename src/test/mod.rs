@@ -1,15 +1,23 @@
-use crate::test::outcome::Termination;
+use crate::test::{exception::Fault, outcome::Termination};
 use core::mem::MaybeUninit;
 
+pub mod exception;
+pub mod filter;
 pub mod outcome;
 pub mod output;
+pub mod panic;
 pub mod runner;
+pub mod shuffle;
 
 /// A standard test.
 ///
 /// This struct is created by the `#[test]` attribute. This struct is not to be used directly and
 /// is not considered part of the public API. If you want to use a similar struct, you should
 /// define one locally and implement `TestCase` for it directly.
+///
+/// `kunit_macros` fills this struct in with a struct literal, so it only has the fields which
+/// the `#[kunit]` attribute sets. Settings which the attribute cannot set yet are added by
+/// wrapping it in a [`TestWithOptions`].
 #[doc(hidden)]
 pub struct Test<T> {
     /// The test name.
@@ -24,6 +32,76 @@ pub struct Test<T> {
     pub should_panic: ShouldPanic,
 }
 
+impl<T> Test<T> {
+    /// Creates a test which is run, and expected to return without panicking or faulting, with
+    /// the default timeout.
+    pub const fn new(name: &'static str, modules: &'static str, test: fn() -> T) -> Self {
+        Test {
+            name,
+            modules,
+            test,
+            ignore: Ignore::No,
+            should_panic: ShouldPanic::No,
+        }
+    }
+
+    /// Sets whether the test is excluded.
+    pub const fn with_ignore(mut self, ignore: Ignore) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Sets whether the test is expected to panic.
+    pub const fn with_should_panic(mut self, should_panic: ShouldPanic) -> Self {
+        self.should_panic = should_panic;
+        self
+    }
+
+    /// Sets whether the test is expected to raise a CPU exception.
+    pub const fn with_should_fault(self, should_fault: ShouldFault) -> TestWithOptions<T> {
+        TestWithOptions::new(self).with_should_fault(should_fault)
+    }
+
+    /// Sets the timeout of the test in milliseconds, overriding the default from `KlibConfig`.
+    pub const fn with_timeout_ms(self, timeout_ms: u64) -> TestWithOptions<T> {
+        TestWithOptions::new(self).with_timeout_ms(timeout_ms)
+    }
+}
+
+/// A [`Test`] with settings which the `#[kunit]` attribute cannot set yet, such as an expected
+/// CPU exception or a timeout.
+///
+/// These settings are kept out of `Test` itself, so that the struct literal which `kunit_macros`
+/// generates for `Test` still compiles. Like `Test`, it is registered with `#[test_case]`.
+pub struct TestWithOptions<T> {
+    test: Test<T>,
+    should_fault: ShouldFault,
+    timeout_ms: Option<u64>,
+}
+
+impl<T> TestWithOptions<T> {
+    /// Wraps a test, with the default settings.
+    pub const fn new(test: Test<T>) -> Self {
+        TestWithOptions {
+            test,
+            should_fault: ShouldFault::No,
+            timeout_ms: None,
+        }
+    }
+
+    /// Sets whether the test is expected to raise a CPU exception.
+    pub const fn with_should_fault(mut self, should_fault: ShouldFault) -> Self {
+        self.should_fault = should_fault;
+        self
+    }
+
+    /// Sets the timeout of the test in milliseconds, overriding the default from `KlibConfig`.
+    pub const fn with_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = Some(timeout_ms);
+        self
+    }
+}
+
 /// A trait representing a test case that can be run and provides metadata about itself.
 pub trait TestCase {
     /// Returns the full name of the test case, including module path (e.g., "my_crate::tests::my_test").
@@ -61,6 +139,16 @@ pub trait TestCase {
 
     /// Whether the test is expected to panic.
     fn should_panic(&self) -> ShouldPanic;
+
+    /// Whether the test is expected to raise a CPU exception.
+    fn should_fault(&self) -> ShouldFault {
+        ShouldFault::No
+    }
+
+    /// The timeout for this test in milliseconds, if it overrides the default timeout.
+    fn timeout_ms(&self) -> Option<u64> {
+        None
+    }
 }
 
 impl<T> TestCase for Test<T>
@@ -92,6 +180,43 @@ where
     }
 }
 
+impl<T> TestCase for TestWithOptions<T>
+where
+    T: Termination,
+{
+    fn run(&self) {
+        self.test.run();
+    }
+
+    fn qualified_name(&self) -> &'static str {
+        self.test.qualified_name()
+    }
+
+    fn name(&self) -> &'static str {
+        self.test.name()
+    }
+
+    fn modules(&self) -> Option<&'static str> {
+        self.test.modules()
+    }
+
+    fn ignore(&self) -> Ignore {
+        self.test.ignore()
+    }
+
+    fn should_panic(&self) -> ShouldPanic {
+        self.test.should_panic()
+    }
+
+    fn should_fault(&self) -> ShouldFault {
+        self.should_fault
+    }
+
+    fn timeout_ms(&self) -> Option<u64> {
+        self.timeout_ms
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Ignore {
     /// The test should be run.
@@ -106,6 +231,21 @@ pub enum ShouldPanic {
     No,
     /// The test is expected to panic during execution.
     Yes,
+    /// The test is expected to panic with a message containing the given substring. This is set
+    /// by the `#[should_panic(expected = "...")]` attribute, which depends on `kunit_macros`
+    /// parsing `expected`, or with [`Test::with_should_panic`].
+    YesWithMessage(&'static str),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ShouldFault {
+    /// The test is not expected to raise a CPU exception.
+    No,
+    /// The test is expected to raise a CPU exception of any kind.
+    Yes,
+    /// The test is expected to raise a CPU exception of the given class, e.g.
+    /// `ShouldFault::YesWithFault(Fault::PageFault)` for a test which touches a guard page.
+    YesWithFault(Fault),
 }
 
 #[doc(hidden)]
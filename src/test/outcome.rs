@@ -40,6 +40,8 @@ pub enum TestResult {
     Success,
     Failure,
     Ignore,
+    Timeout,
+    Filtered,
 }
 
 impl TestResult {
@@ -54,4 +56,59 @@ impl TestResult {
     pub fn is_failure(&self) -> bool {
         matches!(self, TestResult::Failure)
     }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, TestResult::Timeout)
+    }
+
+    pub fn is_filtered(&self) -> bool {
+        matches!(self, TestResult::Filtered)
+    }
+}
+
+/// Running totals of test results for a test group.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TestTotals {
+    /// The number of tests which passed.
+    pub passed: usize,
+    /// The number of tests which failed.
+    pub failed: usize,
+    /// The number of tests which were ignored.
+    pub ignored: usize,
+    /// The number of tests which were stopped after exceeding their timeout.
+    pub timed_out: usize,
+    /// The number of tests which were not run because of the test filters.
+    pub filtered: usize,
+    /// The sum of the cycle counts of all tests.
+    pub total_cycles: u64,
+}
+
+impl TestTotals {
+    pub const fn new() -> Self {
+        TestTotals {
+            passed: 0,
+            failed: 0,
+            ignored: 0,
+            timed_out: 0,
+            filtered: 0,
+            total_cycles: 0,
+        }
+    }
+
+    /// Counts the given result and cycle count towards the totals.
+    pub fn record(&mut self, result: &TestResult, cycle_count: u64) {
+        self.total_cycles = self.total_cycles.saturating_add(cycle_count);
+        match result {
+            TestResult::Success => self.passed += 1,
+            TestResult::Failure => self.failed += 1,
+            TestResult::Ignore => self.ignored += 1,
+            TestResult::Timeout => self.timed_out += 1,
+            TestResult::Filtered => self.filtered += 1,
+        }
+    }
+
+    /// Whether any test has failed or timed out.
+    pub fn has_failures(&self) -> bool {
+        self.failed > 0 || self.timed_out > 0
+    }
 }
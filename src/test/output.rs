@@ -1,96 +1,296 @@
-use crate::{debugcon_println, MAX_STRING_LENGTH};
-use heapless::{format, String};
+//! The JSONL events written by the runner to the output sink (the debug console by default).
+//!
+//! Every row of output is described by a variant of [`Event`], and [`write_event`] is the only
+//! place where rows are serialized. The format is versioned by [`SCHEMA_VERSION`] and described
+//! in machine-readable form by [`SCHEMA_DEFINITION`] (a JSON Schema document). Consumers should
+//! ignore fields they do not know, so that new fields can be added within a schema version;
+//! removing or changing a field requires a new schema version.
+//!
+//! Instead of JSONL, the events can also be written in another [`OutputFormat`] (TAP or libtest
+//! JSON), selected in the `KlibConfig`.
 
-/// Writes a JSON object indicating the start of a test group with its name and test count.
-pub fn write_test_group(test_group: &str, test_count: usize) {
-    let test_group_json: String<MAX_STRING_LENGTH> = format!(
-        r#"{{ "test_group": "{}", "test_count": {} }}"#,
-        test_group, test_count
-    )
-    .unwrap();
-    let test_group_json = replace_heapless_string(&test_group_json, "\n", "").unwrap();
-    let test_group_json = replace_heapless_string(&test_group_json, "\t", "").unwrap();
+use crate::{
+    args,
+    json::JsonWriter,
+    print::SinkWriter,
+    sink::{DebugCon, OutputSink},
+    test::{exception::ExceptionInfo, outcome::TestTotals},
+};
+use conquer_once::spin::OnceCell;
+use core::fmt::{self, Write};
+use kunit_events::{field, Outcome};
 
-    debugcon_println!("{}", test_group_json);
-}
+mod libtest;
+mod tap;
+
+/// The version of the JSONL output written by this module (defined in `kunit_events`, together
+/// with the field names and result spellings shared with the host tools).
+pub use kunit_events::SCHEMA_VERSION;
+
+/// The JSON Schema definition of every row written by this module, for the current
+/// [`SCHEMA_VERSION`]. The same file is shipped with the crate as `schema/events.schema.json`.
+pub const SCHEMA_DEFINITION: &str = include_str!("../../schema/events.schema.json");
 
-/// Writes a JSON object indicating the success of a test case, including its name and cycle count.
-pub fn write_test_success(test_name: &str, _cycle_count: u64) {
-    let test_json: String<MAX_STRING_LENGTH> = format!(
-        r#"
-{{
-    "test": "{}",
-    "result": "pass",
-    "cycle_count": 0
-}}"#,
-        test_name
-    )
-    .unwrap();
-    let test_json = replace_heapless_string(&test_json, "\n", "").unwrap();
-    let test_json = replace_heapless_string(&test_json, "   ", "").unwrap();
+/// The frequency of the cycle counter in Hz, for converting cycle counts to times.
+static CYCLE_FREQUENCY: OnceCell<u64> = OnceCell::uninit();
 
-    debugcon_println!("{}", test_json);
+/// The format in which the runner writes its output to the debug console.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON object per line, as described by [`SCHEMA_DEFINITION`]. This is the format
+    /// expected by the `k1` host runner.
+    #[default]
+    Json,
+    /// TAP version 14, for consumers which do not use `k1`.
+    Tap,
+    /// The (unstable) JSON format of libtest, as written by `cargo test -- --format json
+    /// --report-time`, for existing Rust test tooling. Test starts are only written for tests
+    /// which are not filtered out, as in libtest.
+    LibtestJson,
 }
 
-/// Writes a JSON object indicating the ignore of a test case, including its name and cycle count.
-pub fn write_test_ignore(test_name: &str) {
-    let test_json: String<MAX_STRING_LENGTH> = format!(
-        r#"
-{{
-    "test": "{}",
-    "result": "ignore",
-    "cycle_count": 0
-}}"#,
-        test_name
-    )
-    .unwrap();
-    let test_json = replace_heapless_string(&test_json, "\n", "").unwrap();
-    let test_json = replace_heapless_string(&test_json, "   ", "").unwrap();
+/// A single row of JSONL output.
+pub enum Event<'a> {
+    /// The first row of a test run, with the test group name and test count. If the tests run
+    /// in shuffled order, the seed is included so that the order can be reproduced. The cycle
+    /// counter frequency is included as well, so that cycle counts can be converted to times.
+    Group {
+        test_group: &'a str,
+        test_count: usize,
+        /// The number of tests which are not filtered out (not part of the JSONL row).
+        selected_count: usize,
+        shuffle_seed: Option<u64>,
+    },
+    /// The first row of a test listing (rather than a test run), with the test group name and
+    /// the number of tests listed.
+    ListGroup {
+        test_group: &'a str,
+        test_count: usize,
+    },
+    /// A test case described without running it, with its qualified name, module path, function
+    /// name, and whether it is ignored or expected to panic (or to raise a CPU exception).
+    Listing {
+        test: &'a str,
+        module: &'a str,
+        name: &'a str,
+        ignore: bool,
+        should_panic: bool,
+        expected: Option<&'a str>,
+        should_fault: Option<&'a str>,
+    },
+    /// A test case is about to start. This allows a crash or hang to be attributed to the test
+    /// which was running. Tests which are filtered out have no start row (since schema
+    /// version 4).
+    Start { test: &'a str },
+    /// A test case passed.
+    Pass { test: &'a str, cycle_count: u64 },
+    /// A test case was ignored. Ignored tests are never run, so their cycle count is always zero.
+    Ignore { test: &'a str },
+    /// A test case was not run because of the test filters. Filtered tests are never run, so
+    /// their cycle count is always zero.
+    Filtered { test: &'a str },
+    /// A test case failed, with the location and message of the failure.
+    Fail {
+        test: &'a str,
+        cycle_count: u64,
+        location: &'a str,
+        message: &'a str,
+    },
+    /// A test case was stopped after exceeding its timeout.
+    Timeout {
+        test: &'a str,
+        cycle_count: u64,
+        timeout_ms: u64,
+    },
+    /// A test case failed because it raised a CPU exception which was not expected (only if
+    /// exceptions are caught, or the test expects a different one). This is a `"fail"` row, with
+    /// the exception details as additional fields.
+    Exception {
+        test: &'a str,
+        cycle_count: u64,
+        message: &'a str,
+        exception: &'a ExceptionInfo,
+    },
+    /// The last row of a test run, with the totals of all test results. Since this is written
+    /// last, its presence shows that the output was not cut off.
+    Summary { totals: &'a TestTotals },
+}
 
-    debugcon_println!("{}", test_json);
+/// Writes the given event to the output sink from the `KlibConfig` (the debug console by
+/// default), in the configured output format (one line of JSON by default).
+pub fn write_event(event: &Event) {
+    let mut out = SinkWriter(output_sink());
+    let result = match output_format() {
+        OutputFormat::Json => write_json(out, event),
+        OutputFormat::Tap => tap::write_event(&mut out, event),
+        OutputFormat::LibtestJson => libtest::write_event(&mut out, event),
+    };
+    result.expect("Writing to output sink failed");
 }
 
-/// Writes a JSON object indicating the failure of a test case, including its name, location, and failure message.
-pub fn write_test_failure(test_name: &str, location: &str, message: &str) {
-    let location = replace_heapless_string(
-        &String::<MAX_STRING_LENGTH>::try_from(location).unwrap(),
-        "\\",
-        "/",
-    )
-    .unwrap(); // prevents escape issues with heapless String
+/// Writes a line explaining why the runner cannot run the tests, before it exits with a harness
+/// error. This is not an event (and not valid JSON), but a message for whoever reads the output;
+/// in TAP it is a `Bail out!` line.
+pub fn write_error(message: fmt::Arguments) {
+    let mut out = SinkWriter(output_sink());
+    let result = match output_format() {
+        OutputFormat::Tap => writeln!(out, "Bail out! {message}"),
+        OutputFormat::Json | OutputFormat::LibtestJson => writeln!(out, "error: {message}"),
+    };
+    result.expect("Writing to output sink failed");
+}
 
-    let test_json: String<MAX_STRING_LENGTH> = format!(
-        r#"
-{{
-    "test": "{}",
-    "result": "fail",
-    "cycle_count": 0,
-    "location": "{}",
-    "message": "{}"
-}}"#,
-        test_name, location, message
-    )
-    .unwrap();
-    let test_json = replace_heapless_string(&test_json, "\n", "").unwrap();
-    let test_json = replace_heapless_string(&test_json, "   ", "").unwrap();
+/// Gets the frequency of the cycle counter in Hz, measuring it on first use. This is done when
+/// the group header is written, rather than in the middle of the tests.
+fn cycle_frequency() -> u64 {
+    *CYCLE_FREQUENCY.get_or_init(crate::arch::cycle_frequency)
+}
 
-    debugcon_println!("{}", test_json);
+/// Gets the configured output format, defaulting to JSON if there is no `KlibConfig`.
+fn output_format() -> OutputFormat {
+    args::get_klib_config().map_or(OutputFormat::default(), |config| config.output_format)
 }
 
-/// Helper function to replace all occurrences of a substring in a heapless String
-fn replace_heapless_string(
-    original: &String<MAX_STRING_LENGTH>,
-    from: &str,
-    to: &str,
-) -> Result<String<MAX_STRING_LENGTH>, heapless::string::FromUtf16Error> {
-    let mut new_string: String<MAX_STRING_LENGTH> = String::new();
-    let mut last_end = 0;
+/// Gets the configured output sink, defaulting to the debug console if there is no `KlibConfig`.
+fn output_sink() -> &'static dyn OutputSink {
+    args::get_klib_config().map_or(&DebugCon, |config| config.output_sink)
+}
+
+/// Writes the given event as one line of JSON.
+fn write_json<W: fmt::Write>(out: W, event: &Event) -> fmt::Result {
+    let mut json = JsonWriter::new(out);
+    json.begin_object()?;
+    write_fields(&mut json, event)?;
+    json.end_object()?;
+    json.end_line()
+}
 
-    for (start, _) in original.match_indices(from) {
-        new_string.push_str(&original[last_end..start])?;
-        new_string.push_str(to)?;
-        last_end = start + from.len();
+/// Writes the fields of the given event, without the surrounding braces.
+fn write_fields<W: fmt::Write>(json: &mut JsonWriter<W>, event: &Event) -> fmt::Result {
+    match *event {
+        Event::Group {
+            test_group,
+            test_count,
+            shuffle_seed,
+            ..
+        } => {
+            json.field_str(field::TEST_GROUP, test_group)?;
+            json.field_u64(field::TEST_COUNT, test_count as u64)?;
+            json.field_u64(field::SCHEMA, SCHEMA_VERSION)?;
+            if let Some(shuffle_seed) = shuffle_seed {
+                json.field_u64(field::SHUFFLE_SEED, shuffle_seed)?;
+            }
+            match cycle_frequency() {
+                0 => Ok(()),
+                frequency => json.field_u64(field::CYCLE_FREQUENCY, frequency),
+            }
+        }
+        Event::ListGroup {
+            test_group,
+            test_count,
+        } => {
+            json.field_str(field::TEST_GROUP, test_group)?;
+            json.field_u64(field::TEST_COUNT, test_count as u64)?;
+            json.field_u64(field::SCHEMA, SCHEMA_VERSION)?;
+            json.field_str(field::MODE, kunit_events::MODE_LIST)
+        }
+        Event::Listing {
+            test,
+            module,
+            name,
+            ignore,
+            should_panic,
+            expected,
+            should_fault,
+        } => {
+            json.field_str(field::TEST, test)?;
+            json.field_str(field::MODULE, module)?;
+            json.field_str(field::NAME, name)?;
+            json.field_bool(field::IGNORE, ignore)?;
+            json.field_bool(field::SHOULD_PANIC, should_panic)?;
+            if let Some(expected) = expected {
+                json.field_str(field::EXPECTED, expected)?;
+            }
+            if let Some(should_fault) = should_fault {
+                json.field_str(field::SHOULD_FAULT, should_fault)?;
+            }
+            Ok(())
+        }
+        Event::Start { test } => {
+            json.field_str(field::EVENT, kunit_events::EVENT_START)?;
+            json.field_str(field::TEST, test)
+        }
+        Event::Pass { test, cycle_count } => write_result(json, test, Outcome::Pass, cycle_count),
+        Event::Ignore { test } => write_result(json, test, Outcome::Ignore, 0),
+        Event::Filtered { test } => write_result(json, test, Outcome::Filtered, 0),
+        Event::Fail {
+            test,
+            cycle_count,
+            location,
+            message,
+        } => {
+            write_result(json, test, Outcome::Fail, cycle_count)?;
+            json.field_str(field::LOCATION, location)?;
+            json.field_str(field::MESSAGE, message)
+        }
+        Event::Timeout {
+            test,
+            cycle_count,
+            timeout_ms,
+        } => {
+            write_result(json, test, Outcome::Timeout, cycle_count)?;
+            json.field_u64(field::TIMEOUT_MS, timeout_ms)
+        }
+        Event::Exception {
+            test,
+            cycle_count,
+            message,
+            exception,
+        } => {
+            write_result(json, test, Outcome::Fail, cycle_count)?;
+            json.key(field::LOCATION)?;
+            json.string_fmt(format_args!("{:#x}", exception.instruction_pointer))?;
+            json.field_str(field::MESSAGE, message)?;
+            json.field_str(field::EXCEPTION, exception.kind)?;
+            if let Some(address) = exception.address {
+                json.key(field::ADDRESS)?;
+                json.string_fmt(format_args!("{address:#x}"))?;
+            }
+            if let Some(error_code) = exception.error_code {
+                json.key(field::ERROR_CODE)?;
+                json.string_fmt(format_args!("{error_code:#x}"))?;
+            }
+            json.key(field::REGISTERS)?;
+            json.begin_object()?;
+            for &(name, value) in &exception.registers {
+                json.key(name)?;
+                json.string_fmt(format_args!("{value:#018x}"))?;
+            }
+            json.end_object()
+        }
+        Event::Summary { totals } => {
+            json.key(field::SUMMARY)?;
+            json.begin_object()?;
+            json.field_u64(field::PASSED, totals.passed as u64)?;
+            json.field_u64(field::FAILED, totals.failed as u64)?;
+            json.field_u64(field::IGNORED, totals.ignored as u64)?;
+            json.field_u64(field::TIMED_OUT, totals.timed_out as u64)?;
+            json.field_u64(field::FILTERED, totals.filtered as u64)?;
+            json.field_u64(field::TOTAL_CYCLES, totals.total_cycles)?;
+            json.field_bool(field::COMPLETE, true)?;
+            json.end_object()
+        }
     }
-    new_string.push_str(&original[last_end..])?;
-    Ok(new_string)
+}
+
+/// Writes the fields shared by every test result row.
+fn write_result<W: fmt::Write>(
+    json: &mut JsonWriter<W>,
+    test: &str,
+    result: Outcome,
+    cycle_count: u64,
+) -> fmt::Result {
+    json.field_str(field::TEST, test)?;
+    json.field_str(field::RESULT, result.as_str())?;
+    json.field_u64(field::CYCLE_COUNT, cycle_count)
 }
@@ -0,0 +1,139 @@
+//! The libtest JSON rendering of the output events, as written by `cargo test -- --format json
+//! --report-time -Z unstable-options`.
+//!
+//! Tools which consume that format (IDEs, `cargo2junit`, ...) can read kernel test results
+//! unchanged. Execution times are converted from cycles to seconds using the cycle counter
+//! frequency, which is determined once when the suite starts.
+
+use super::Event;
+use crate::json::JsonWriter;
+use crate::test::exception::ExceptionInfo;
+use core::fmt::{self, Write};
+
+/// Writes the given event as one line of libtest JSON. Events without a libtest equivalent
+/// (such as filtered tests, which libtest only counts) are not written at all.
+pub(super) fn write_event<W: Write>(out: &mut W, event: &Event) -> fmt::Result {
+    match *event {
+        Event::ListGroup { .. } | Event::Filtered { .. } => Ok(()),
+        // libtest lists tests as plain text, even in JSON mode
+        Event::Listing { test, .. } => writeln!(out, "{test}: test"),
+        _ => {
+            let mut json = JsonWriter::new(out);
+            json.begin_object()?;
+            write_fields(&mut json, event)?;
+            json.end_object()?;
+            json.end_line()
+        }
+    }
+}
+
+/// Writes the fields of the given event, without the surrounding braces.
+fn write_fields<W: Write>(json: &mut JsonWriter<W>, event: &Event) -> fmt::Result {
+    match *event {
+        Event::Group {
+            selected_count,
+            shuffle_seed,
+            ..
+        } => {
+            // measure the frequency up front, rather than in the middle of the tests
+            super::cycle_frequency();
+
+            json.field_str("type", "suite")?;
+            json.field_str("event", "started")?;
+            json.field_u64("test_count", selected_count as u64)?;
+            if let Some(shuffle_seed) = shuffle_seed {
+                json.field_u64("shuffle_seed", shuffle_seed)?;
+            }
+            Ok(())
+        }
+        Event::ListGroup { .. } | Event::Listing { .. } | Event::Filtered { .. } => Ok(()),
+        Event::Start { test } => {
+            json.field_str("type", "test")?;
+            json.field_str("event", "started")?;
+            json.field_str("name", test)
+        }
+        Event::Pass { test, cycle_count } => {
+            write_test_result(json, test, "ok")?;
+            write_exec_time(json, cycle_count)
+        }
+        Event::Ignore { test } => write_test_result(json, test, "ignored"),
+        Event::Fail {
+            test,
+            cycle_count,
+            location,
+            message,
+        } => {
+            write_test_result(json, test, "failed")?;
+            write_exec_time(json, cycle_count)?;
+            json.key("stdout")?;
+            json.string_fmt(format_args!(
+                "thread '{test}' panicked at {location}:\n{message}\n"
+            ))
+        }
+        Event::Exception {
+            test,
+            cycle_count,
+            message,
+            exception,
+        } => {
+            write_test_result(json, test, "failed")?;
+            write_exec_time(json, cycle_count)?;
+            json.key("stdout")?;
+            json.string_fmt(format_args!(
+                "thread '{test}' raised a CPU exception at {:#x}:\n{message}\n{}",
+                exception.instruction_pointer,
+                RegisterDump(exception)
+            ))
+        }
+        Event::Timeout {
+            test, cycle_count, ..
+        } => {
+            write_test_result(json, test, "failed")?;
+            write_exec_time(json, cycle_count)?;
+            json.field_str("reason", "time limit exceeded")
+        }
+        Event::Summary { totals } => {
+            let failed = totals.failed + totals.timed_out;
+            json.field_str("type", "suite")?;
+            json.field_str("event", if failed > 0 { "failed" } else { "ok" })?;
+            json.field_u64("passed", totals.passed as u64)?;
+            json.field_u64("failed", failed as u64)?;
+            json.field_u64("ignored", totals.ignored as u64)?;
+            json.field_u64("measured", 0)?;
+            json.field_u64("filtered_out", totals.filtered as u64)?;
+            write_exec_time(json, totals.total_cycles)
+        }
+    }
+}
+
+/// Writes the fields shared by every test result, up to and including the `event` field.
+fn write_test_result<W: Write>(json: &mut JsonWriter<W>, test: &str, event: &str) -> fmt::Result {
+    json.field_str("type", "test")?;
+    json.field_str("name", test)?;
+    json.field_str("event", event)
+}
+
+/// Formats the register dump of an exception, one register per line.
+struct RegisterDump<'a>(&'a ExceptionInfo);
+
+impl fmt::Display for RegisterDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &(name, value) in &self.0.registers {
+            writeln!(f, "{name:>8}: {value:#018x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the `exec_time` field, in seconds, for the given cycle count.
+fn write_exec_time<W: Write>(json: &mut JsonWriter<W>, cycle_count: u64) -> fmt::Result {
+    let frequency = super::cycle_frequency();
+    let nanoseconds = if frequency == 0 {
+        0
+    } else {
+        (cycle_count as u128 * 1_000_000_000 / frequency as u128) as u64
+    };
+
+    json.key("exec_time")?;
+    json.fixed_point(nanoseconds, 9)
+}
@@ -0,0 +1,118 @@
+//! The TAP version 14 (Test Anything Protocol) rendering of the output events.
+//!
+//! The plan line comes straight after the version line, since the test count is known up front.
+//! Failures carry a YAML diagnostic block; its strings are written with JSON escaping, which is
+//! also valid for YAML double-quoted scalars.
+
+use super::Event;
+use crate::json::JsonWriter;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of test points written so far. TAP numbers test points in output order, which
+/// differs from declaration order when tests are shuffled.
+static TEST_POINTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes the given event as TAP. Events without a TAP equivalent (such as test starts) are not
+/// written at all.
+pub(super) fn write_event<W: Write>(out: &mut W, event: &Event) -> fmt::Result {
+    match *event {
+        Event::Group {
+            test_group,
+            test_count,
+            shuffle_seed,
+            ..
+        } => {
+            writeln!(out, "TAP version 14")?;
+            writeln!(out, "1..{test_count}")?;
+            writeln!(out, "# test group: {test_group}")?;
+            if let Some(shuffle_seed) = shuffle_seed {
+                writeln!(out, "# shuffle seed: {shuffle_seed}")?;
+            }
+            Ok(())
+        }
+        Event::ListGroup {
+            test_group,
+            test_count,
+        } => {
+            writeln!(out, "TAP version 14")?;
+            writeln!(out, "1..0 # SKIP listing {test_count} tests")?;
+            writeln!(out, "# test group: {test_group}")
+        }
+        Event::Listing { test, .. } => writeln!(out, "# {test}"),
+        Event::Start { .. } => Ok(()),
+        Event::Pass { test, .. } => write_test_point(out, true, test, None),
+        Event::Ignore { test } => write_test_point(out, true, test, Some("ignored")),
+        Event::Filtered { test } => write_test_point(out, true, test, Some("filtered")),
+        Event::Fail {
+            test,
+            cycle_count,
+            location,
+            message,
+        } => {
+            write_test_point(out, false, test, None)?;
+            writeln!(out, "  ---")?;
+            write_yaml_str(out, "message", message)?;
+            write_yaml_str(out, "at", location)?;
+            writeln!(out, "  cycle_count: {cycle_count}")?;
+            writeln!(out, "  ...")
+        }
+        Event::Timeout {
+            test,
+            cycle_count,
+            timeout_ms,
+        } => {
+            write_test_point(out, false, test, None)?;
+            writeln!(out, "  ---")?;
+            writeln!(out, "  message: \"test exceeded its timeout\"")?;
+            writeln!(out, "  timeout_ms: {timeout_ms}")?;
+            writeln!(out, "  cycle_count: {cycle_count}")?;
+            writeln!(out, "  ...")
+        }
+        Event::Exception {
+            test,
+            cycle_count,
+            message,
+            exception,
+        } => {
+            write_test_point(out, false, test, None)?;
+            writeln!(out, "  ---")?;
+            write_yaml_str(out, "message", message)?;
+            writeln!(out, "  at: \"{:#x}\"", exception.instruction_pointer)?;
+            writeln!(out, "  cycle_count: {cycle_count}")?;
+            writeln!(out, "  registers:")?;
+            for &(name, value) in &exception.registers {
+                writeln!(out, "    {name}: \"{value:#018x}\"")?;
+            }
+            writeln!(out, "  ...")
+        }
+        Event::Summary { totals } => writeln!(
+            out,
+            "# passed {}, failed {}, ignored {}, timed out {}, filtered {}",
+            totals.passed, totals.failed, totals.ignored, totals.timed_out, totals.filtered
+        ),
+    }
+}
+
+/// Writes an `ok` or `not ok` line for the next test point, with an optional `# SKIP` reason.
+fn write_test_point<W: Write>(
+    out: &mut W,
+    ok: bool,
+    test: &str,
+    skip_reason: Option<&str>,
+) -> fmt::Result {
+    let number = TEST_POINTS.fetch_add(1, Ordering::SeqCst) + 1;
+    let status = if ok { "ok" } else { "not ok" };
+    write!(out, "{status} {number} - {test}")?;
+    if let Some(skip_reason) = skip_reason {
+        write!(out, " # SKIP {skip_reason}")?;
+    }
+    writeln!(out)
+}
+
+/// Writes an indented YAML key with a double-quoted string value.
+fn write_yaml_str<W: Write>(out: &mut W, key: &str, value: &str) -> fmt::Result {
+    write!(out, "  {key}: ")?;
+    JsonWriter::new(&mut *out).string(value)?;
+    writeln!(out)
+}
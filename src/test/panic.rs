@@ -0,0 +1,139 @@
+use crate::MAX_STRING_LENGTH;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use heapless::String;
+
+/// Marker appended to a panic message which was too long to be captured in full.
+pub const TRUNCATION_MARKER: &str = "... [truncated]";
+
+/// The details of a panic, rendered into fixed-size strings for reporting.
+pub struct PanicDetails {
+    /// The source location of the panic, formatted as `file:line`.
+    pub location: String<MAX_STRING_LENGTH>,
+    /// The fully formatted panic message, truncated if it does not fit.
+    pub message: String<MAX_STRING_LENGTH>,
+}
+
+/// Formats the given arguments into a fixed-size string. Output that does not fit is cut off and
+/// ends with [`TRUNCATION_MARKER`].
+pub fn format_truncated(args: fmt::Arguments) -> String<MAX_STRING_LENGTH> {
+    let mut writer = TruncatingWriter::new();
+    let _ = writer.write_fmt(args);
+    writer.finish()
+}
+
+impl PanicDetails {
+    /// Renders the location and message of the given panic.
+    ///
+    /// The message is formatted through its `Display` implementation, so formatted panics (such
+    /// as failed `assert_eq!` calls) keep their arguments. Messages longer than the maximum string
+    /// length are cut off and end with [`TRUNCATION_MARKER`].
+    pub fn from_info(info: &PanicInfo) -> Self {
+        let mut location = TruncatingWriter::new();
+        match info.location() {
+            Some(loc) => {
+                let _ = write!(location, "{}:{}", loc.file(), loc.line());
+            }
+            None => {
+                let _ = location.write_str("unknown location");
+            }
+        }
+
+        PanicDetails {
+            location: location.finish(),
+            message: format_truncated(format_args!("{}", info.message())),
+        }
+    }
+}
+
+/// Whether the message of the given panic contains the expected substring.
+///
+/// The message is matched while it is formatted, so that a substring beyond the length at which
+/// [`PanicDetails::message`] is cut off is still found.
+pub fn message_contains(info: &PanicInfo, expected: &str) -> bool {
+    let mut matcher = SubstringMatcher {
+        expected: expected.as_bytes(),
+        matched: 0,
+    };
+    let _ = write!(matcher, "{}", info.message());
+    matcher.matched == expected.len()
+}
+
+/// A writer which searches the written text for a substring, without storing the text.
+struct SubstringMatcher<'a> {
+    expected: &'a [u8],
+    /// The length of the longest prefix of `expected` which the text written so far ends with,
+    /// or the length of `expected` once it was found.
+    matched: usize,
+}
+
+impl Write for SubstringMatcher<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.matched == self.expected.len() {
+                return Ok(());
+            }
+            // on a mismatch, fall back to the longest prefix of `expected` which is also a suffix
+            // of the part matched so far (that part is the end of the text written so far)
+            while self.matched > 0 && self.expected[self.matched] != byte {
+                let matched = &self.expected[..self.matched];
+                self.matched = (0..self.matched)
+                    .rev()
+                    .find(|&length| matched.ends_with(&self.expected[..length]))
+                    .unwrap_or(0);
+            }
+            if self.expected[self.matched] == byte {
+                self.matched += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A writer which fills a fixed-size string and silently drops anything that does not fit,
+/// leaving room for the truncation marker.
+struct TruncatingWriter {
+    buffer: String<MAX_STRING_LENGTH>,
+    truncated: bool,
+}
+
+impl TruncatingWriter {
+    const fn new() -> Self {
+        TruncatingWriter {
+            buffer: String::new(),
+            truncated: false,
+        }
+    }
+
+    /// Returns the written string, with the truncation marker appended if anything was dropped.
+    fn finish(mut self) -> String<MAX_STRING_LENGTH> {
+        if self.truncated {
+            // room for the marker was reserved while writing
+            let _ = self.buffer.push_str(TRUNCATION_MARKER);
+        }
+        self.buffer
+    }
+}
+
+impl Write for TruncatingWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+
+        let limit = MAX_STRING_LENGTH - TRUNCATION_MARKER.len();
+        if self.buffer.len() + s.len() <= limit {
+            let _ = self.buffer.push_str(s);
+            return Ok(());
+        }
+
+        for c in s.chars() {
+            if self.buffer.len() + c.len_utf8() > limit {
+                self.truncated = true;
+                break;
+            }
+            let _ = self.buffer.push(c);
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,16 @@
 use crate::{
     args, qemu,
-    test::{self, outcome::TestResult, Ignore, ShouldPanic, TestCase},
+    test::{
+        self,
+        exception::ExceptionInfo,
+        outcome::{TestResult, TestTotals},
+        output::{write_event, Event},
+        Ignore, ShouldFault, ShouldPanic, TestCase,
+    },
     MAX_STRING_LENGTH,
 };
 use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use heapless::{format, String};
 use spin::RwLock;
 
@@ -12,8 +19,9 @@ use spin::RwLock;
 /// OnceCell, Mutex, or RwLock here (at least their no_std variants).
 static mut TESTS: &'static [&'static dyn TestCase] = &[];
 
-/// The global test runner instance. This is initialized once at the start of runner.
-pub static TEST_RUNNER: OnceCell<KernelTestRunner> = OnceCell::uninit();
+/// The global test runner instance. This is initialized once at the start of runner, with the
+/// runner from the `KlibConfig` (or `KernelTestRunner` if there is none).
+pub static TEST_RUNNER: OnceCell<&'static dyn TestRunner> = OnceCell::uninit();
 
 /// Tracker for the curent test index (corresponding to the index in TESTS)
 pub static CURRENT_TEST_INDEX: OnceCell<RwLock<usize>> = OnceCell::new(RwLock::new(0));
@@ -21,6 +29,27 @@ pub static CURRENT_TEST_INDEX: OnceCell<RwLock<usize>> = OnceCell::new(RwLock::n
 /// Tracker for the current module name, to print headers when it changes
 pub static CURRENT_MODULE: OnceCell<RwLock<&'static str>> = OnceCell::new(RwLock::new(""));
 
+/// Tracker for the cycle number at which the current test started, so that the panic handler
+/// can still report how long a failing test ran for
+pub static CURRENT_TEST_CYCLE_START: OnceCell<RwLock<u64>> = OnceCell::new(RwLock::new(0));
+
+/// Tracker for the pass/fail/ignore totals of all tests run so far
+pub static TEST_TOTALS: OnceCell<RwLock<TestTotals>> =
+    OnceCell::new(RwLock::new(TestTotals::new()));
+
+/// The seed for the shuffled test order, or `None` if tests run in declaration order
+static SHUFFLE_SEED: OnceCell<Option<u64>> = OnceCell::uninit();
+
+/// Whether the `after_each` fixture still has to run for the current test
+static TEARDOWN_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Whether a panic is currently being handled, to detect panics inside the panic handler itself
+static HANDLING_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// The stack pointer at the start of runner. After a panic, the stack is reset to this point
+/// before running the next test, so the panicked test's frames are discarded instead of piling up.
+static RUNNER_STACK_POINTER: AtomicUsize = AtomicUsize::new(0);
+
 /// A test runner that runs the given tests and exits QEMU after completion.
 ///
 /// Output from this runner is formatted as line-delimited JSON and printed to the debug
@@ -29,17 +58,49 @@ pub fn runner(tests: &'static [&'static dyn TestCase]) -> ! {
     unsafe {
         TESTS = tests;
     }
+    RUNNER_STACK_POINTER.store(crate::arch::read_stack_pointer(), Ordering::SeqCst);
+    crate::arch::init_watchdog(timeout_handler);
+    crate::arch::init_exception_handler(exception_handler);
+    args::load_test_args();
+    if args::test_args_truncated() {
+        // a cut-off filter would silently run other tests than the ones asked for
+        test::output::write_error(format_args!(
+            "the harness arguments are longer than {MAX_STRING_LENGTH} bytes and were cut off"
+        ));
+        qemu::exit(qemu::ExitCode::HarnessError);
+    }
+    SHUFFLE_SEED.init_once(shuffle_seed_from_args);
+
+    let test_runner = *TEST_RUNNER.get_or_init(|| {
+        args::get_klib_config()
+            .and_then(|config| config.test_runner)
+            .unwrap_or(&KernelTestRunner)
+    });
+    if args::has_test_arg("list") {
+        test_runner.list_tests()
+    }
+    test_runner.run_tests(0)
+}
 
-    TEST_RUNNER.get_or_init(|| KernelTestRunner::default());
-    TEST_RUNNER.get().unwrap().run_tests(0)
+/// Returns the tests passed to runner, in declaration order. This is empty until runner starts.
+pub fn tests() -> &'static [&'static dyn TestCase] {
+    unsafe { TESTS }
 }
 
 /// A trait defining the behavior of a test runner.
-pub trait TestRunner {
+///
+/// `KernelTestRunner` is used unless another implementation is set with
+/// `KlibConfigBuilder::test_runner`. Custom implementations can find the tests with [`tests`],
+/// report results with [`crate::test::output::write_event`] and should continue after a panic
+/// or timeout with [`resume_tests`], which calls `run_tests` with the current test index.
+pub trait TestRunner: Sync {
     /// Runs once before all tests.
     fn before_tests(&self);
     /// Runs all tests starting from the given index.
     fn run_tests(&self, start_index: usize) -> !;
+    /// Lists all tests (honoring the test filters) without running them, then exits. This is
+    /// used instead of `run_tests` when the `kunit.list` harness argument is given.
+    fn list_tests(&self) -> !;
     /// Runs once after all tests.
     fn after_tests(&self) -> !;
     /// Called at the start of each test, returns the starting cycle number.
@@ -50,7 +111,24 @@ pub trait TestRunner {
     fn current_test(&self) -> Option<&'static dyn TestCase>;
     /// Called when a test panics. This should print the panic information, mark the current
     /// test as failed, and continue with the next test (if possible).
+    ///
+    /// This is called from within the panic handler, on top of the panicked test's stack. To
+    /// avoid growing the stack with every failure, implementations should continue on a clean
+    /// stack (see [`resume_tests`]) rather than calling `run_tests` directly.
     fn handle_panic(&self, info: &core::panic::PanicInfo) -> !;
+    /// Called from the watchdog interrupt when a test exceeds its timeout. This should mark the
+    /// current test as timed out and continue with the next test (if possible), in the same way
+    /// as `handle_panic`.
+    fn handle_timeout(&self) -> !;
+    /// Called from the harness exception handlers when a test raises a CPU exception (only if
+    /// `KlibConfig::catch_exceptions` is set). This should mark the current test as failed and
+    /// continue with the next test (if possible), in the same way as `handle_panic`.
+    ///
+    /// The default implementation ends the run with a harness error.
+    fn handle_exception(&self, info: &ExceptionInfo) -> ! {
+        let _ = info;
+        qemu::exit(qemu::ExitCode::HarnessError)
+    }
 }
 
 /// A kernel test runner that runs all tests sequentially and exits QEMU after completion.
@@ -62,7 +140,12 @@ impl TestRunner for KernelTestRunner {
         let test_group = args::get_test_group().unwrap_or("default");
         let tests = unsafe { TESTS };
 
-        test::output::write_test_group(test_group, tests.len());
+        write_event(&Event::Group {
+            test_group,
+            test_count: tests.len(),
+            selected_count: tests.iter().filter(|test| is_selected(**test)).count(),
+            shuffle_seed: shuffle_seed(),
+        });
     }
 
     fn run_tests(&self, start_index: usize) -> ! {
@@ -72,13 +155,65 @@ impl TestRunner for KernelTestRunner {
         }
 
         let tests = unsafe { TESTS };
-        for (i, &test) in tests.iter().enumerate().skip(start_index) {
+        for i in start_index..tests.len() {
+            let test = tests[test_index(i)];
             let cycle_start = self.start_test();
 
+            if !is_selected(test) {
+                self.complete_test(TestResult::Filtered, cycle_start);
+                if !increment_test_index(i) {
+                    break; // no more tests to run
+                }
+                continue;
+            }
+
             match test.ignore() {
                 Ignore::No => {
+                    run_before_each();
+                    if catches_exceptions() || expects_fault(test) {
+                        crate::arch::arm_exception_handlers();
+                    }
+                    if let Some(timeout_ms) = test_timeout_ms(test) {
+                        crate::arch::arm_watchdog(timeout_ms);
+                    }
                     test.run();
-                    self.complete_test(TestResult::Success, cycle_start);
+                    crate::arch::disarm_watchdog();
+                    crate::arch::disarm_exception_handlers();
+                    run_after_each();
+
+                    // reaching this point means the test returned without panicking or faulting,
+                    // so there is no location to report other than the test itself
+                    let location = qualified_test_name(test);
+                    match (test.should_panic(), test.should_fault()) {
+                        (ShouldPanic::No, ShouldFault::No) => {
+                            self.complete_test(TestResult::Success, cycle_start);
+                        }
+                        (_, ShouldFault::No) => {
+                            self.fail_current_test(
+                                cycle_start,
+                                location.as_str(),
+                                "test did not panic as expected",
+                            );
+                        }
+                        (_, ShouldFault::Yes) => {
+                            self.fail_current_test(
+                                cycle_start,
+                                location.as_str(),
+                                "test did not raise a CPU exception as expected",
+                            );
+                        }
+                        (_, ShouldFault::YesWithFault(fault)) => {
+                            let message = test::panic::format_truncated(format_args!(
+                                "test did not raise a CPU exception as expected\n expected fault: {}",
+                                fault.name()
+                            ));
+                            self.fail_current_test(
+                                cycle_start,
+                                location.as_str(),
+                                message.as_str(),
+                            );
+                        }
+                    }
                 }
                 Ignore::Yes => {
                     self.complete_test(TestResult::Ignore, cycle_start);
@@ -92,57 +227,125 @@ impl TestRunner for KernelTestRunner {
         self.after_tests()
     }
 
+    fn list_tests(&self) -> ! {
+        let test_group = args::get_test_group().unwrap_or("default");
+        let tests = unsafe { TESTS };
+
+        let test_count = tests.iter().filter(|test| is_selected(**test)).count();
+        write_event(&Event::ListGroup {
+            test_group,
+            test_count,
+        });
+
+        for &test in tests.iter().filter(|test| is_selected(**test)) {
+            let (should_panic, expected) = match test.should_panic() {
+                ShouldPanic::No => (false, None),
+                ShouldPanic::Yes => (true, None),
+                ShouldPanic::YesWithMessage(expected) => (true, Some(expected)),
+            };
+            let should_fault = match test.should_fault() {
+                ShouldFault::No => None,
+                ShouldFault::Yes => Some("any"),
+                ShouldFault::YesWithFault(fault) => Some(fault.name()),
+            };
+            write_event(&Event::Listing {
+                test: &qualified_test_name(test),
+                module: test.modules().unwrap_or(""),
+                name: test.name(),
+                ignore: matches!(test.ignore(), Ignore::Yes),
+                should_panic,
+                expected,
+                should_fault,
+            });
+        }
+
+        qemu::exit(qemu::ExitCode::Success)
+    }
+
     fn after_tests(&self) -> ! {
+        // leave the last module
+        let last_module = *CURRENT_MODULE.get().unwrap().read();
+        if !last_module.is_empty() {
+            run_after_module(last_module);
+        }
+
+        let totals = *TEST_TOTALS.get().unwrap().read();
+        write_event(&Event::Summary { totals: &totals });
+        if totals.has_failures() {
+            qemu::exit(qemu::ExitCode::Failed)
+        }
+
         qemu::exit(qemu::ExitCode::Success)
     }
 
     fn start_test(&self) -> u64 {
-        // check if the current module has changed; if so, reassign it and print a header
         let current_test = self.current_test().unwrap();
+        let selected = is_selected(current_test);
 
-        let module_path = current_test.modules().unwrap_or("unknown_module");
-        {
-            let mut current_module = CURRENT_MODULE.get().unwrap().write();
-            if *current_module != module_path {
-                *current_module = module_path;
+        // the test starts before its module fixtures run, so that a panic in them is reported
+        // against this test
+        if selected {
+            write_event(&Event::Start {
+                test: &qualified_test_name(current_test),
+            });
+        }
+        *CURRENT_TEST_CYCLE_START.get().unwrap().write() = read_current_cycle();
+
+        // only tests which actually run enter their module, so that the module fixtures do not
+        // run for modules whose tests are all ignored or filtered out
+        if selected && matches!(current_test.ignore(), Ignore::No) {
+            let module_path = current_test.modules().unwrap_or("unknown_module");
+            let previous_module = *CURRENT_MODULE.get().unwrap().read();
+
+            // run the module fixtures (outside of the lock) when the module changes. The module
+            // is only entered once `before_module` returns, so a module is left at most once and
+            // `after_module` never runs for a module whose setup panicked
+            if previous_module != module_path {
+                *CURRENT_MODULE.get().unwrap().write() = "";
+                if !previous_module.is_empty() {
+                    run_after_module(previous_module);
+                }
+                run_before_module(module_path);
+                *CURRENT_MODULE.get().unwrap().write() = module_path;
             }
-        } // scope will release the lock here
+        }
 
-        // return the current cycle (for duration calculation later)
-        read_current_cycle()
+        // record and return the current cycle (for duration calculation later), so that the
+        // duration does not include the module fixtures
+        let cycle_start = read_current_cycle();
+        *CURRENT_TEST_CYCLE_START.get().unwrap().write() = cycle_start;
+        cycle_start
     }
 
     fn complete_test(&self, result: TestResult, cycle_start: u64) {
-        let cycle_count = if cycle_start != u64::MAX {
-            // u64::MAX = unknown
-            read_current_cycle() - cycle_start
-        } else {
-            0
-        };
+        let cycle_count = elapsed_cycles(cycle_start);
+        TEST_TOTALS
+            .get()
+            .unwrap()
+            .write()
+            .record(&result, cycle_count);
 
         match result {
             TestResult::Success => {
-                let current_test = self.current_test().unwrap();
-                let test_name: String<MAX_STRING_LENGTH> = format!(
-                    "{}::{}",
-                    current_test.modules().unwrap(),
-                    current_test.name()
-                )
-                .unwrap();
-                test::output::write_test_success(&test_name, cycle_count);
+                let test_name = qualified_test_name(self.current_test().unwrap());
+                write_event(&Event::Pass {
+                    test: &test_name,
+                    cycle_count,
+                });
             }
             TestResult::Failure => {
                 // panic handler will print [fail] with details (and same for JSON output)
             }
             TestResult::Ignore => {
-                let current_test = self.current_test().unwrap();
-                let test_name: String<MAX_STRING_LENGTH> = format!(
-                    "{}::{}",
-                    current_test.modules().unwrap(),
-                    current_test.name()
-                )
-                .unwrap();
-                test::output::write_test_ignore(&test_name);
+                let test_name = qualified_test_name(self.current_test().unwrap());
+                write_event(&Event::Ignore { test: &test_name });
+            }
+            TestResult::Timeout => {
+                // timeout handler will print the timeout details
+            }
+            TestResult::Filtered => {
+                let test_name = qualified_test_name(self.current_test().unwrap());
+                write_event(&Event::Filtered { test: &test_name });
             }
         }
     }
@@ -150,53 +353,295 @@ impl TestRunner for KernelTestRunner {
     fn current_test(&self) -> Option<&'static dyn TestCase> {
         let current_index = *CURRENT_TEST_INDEX.get().unwrap().read();
         let tests = unsafe { TESTS };
-        tests.get(current_index).copied()
+        if current_index >= tests.len() {
+            return None;
+        }
+        tests.get(test_index(current_index)).copied()
     }
 
     fn handle_panic(&self, info: &core::panic::PanicInfo) -> ! {
+        // a panic while reporting a panic means the runner itself is broken
+        if HANDLING_PANIC.swap(true, Ordering::SeqCst) {
+            qemu::exit(qemu::ExitCode::HarnessError);
+        }
+        crate::arch::disarm_watchdog();
+        crate::arch::disarm_exception_handlers();
+
         // finish the test output, replaces [pass] with panic details
-        let location = if let Some(location) = info.location() {
-            format!("{}:{}", location.file(), location.line()).unwrap()
-        } else {
-            String::<MAX_STRING_LENGTH>::try_from("unknown location").unwrap()
-        };
-        let message = info.message().as_str().unwrap_or("no message");
+        let details = test::panic::PanicDetails::from_info(info);
+        let cycle_start = *CURRENT_TEST_CYCLE_START.get().unwrap().read();
 
-        let current_test = self.current_test().unwrap();
-        let test_name: String<MAX_STRING_LENGTH> = format!(
-            "{}::{}",
-            current_test.modules().unwrap(),
-            current_test.name()
-        )
-        .unwrap();
+        // teardown is guaranteed even for panicked tests; a panic in it ends the run (see above)
+        run_after_each();
+
+        let Some(current_test) = self.current_test() else {
+            // the panic did not happen inside a test, so it cannot be attributed to one
+            qemu::exit(qemu::ExitCode::HarnessError);
+        };
 
         // handle according to whether the test was expected to panic
         match current_test.should_panic() {
             ShouldPanic::No => {
-                test::output::write_test_failure(&test_name, location.as_str(), message);
-                self.complete_test(TestResult::Failure, u64::MAX);
+                self.fail_current_test(
+                    cycle_start,
+                    details.location.as_str(),
+                    details.message.as_str(),
+                );
             }
             ShouldPanic::Yes => {
-                self.complete_test(TestResult::Success, u64::MAX);
+                self.complete_test(TestResult::Success, cycle_start);
+            }
+            ShouldPanic::YesWithMessage(expected) => {
+                if test::panic::message_contains(info, expected) {
+                    self.complete_test(TestResult::Success, cycle_start);
+                } else {
+                    let message = test::panic::format_truncated(format_args!(
+                        "panic did not contain expected string\n      panic message: {:?}\n expected substring: {:?}",
+                        details.message.as_str(),
+                        expected
+                    ));
+                    self.fail_current_test(
+                        cycle_start,
+                        details.location.as_str(),
+                        message.as_str(),
+                    );
+                }
+            }
+        }
+
+        HANDLING_PANIC.store(false, Ordering::SeqCst);
+        self.resume_after_current_test()
+    }
+
+    fn handle_timeout(&self) -> ! {
+        let cycle_start = *CURRENT_TEST_CYCLE_START.get().unwrap().read();
+        crate::arch::disarm_exception_handlers();
+
+        // teardown first, so that a panic in it is reported as the only result for this test
+        run_after_each();
+
+        let Some(current_test) = self.current_test() else {
+            // the watchdog fired outside of a test, so it cannot be attributed to one
+            qemu::exit(qemu::ExitCode::HarnessError);
+        };
+
+        let test_name = qualified_test_name(current_test);
+        let timeout_ms = test_timeout_ms(current_test).unwrap_or(0);
+        write_event(&Event::Timeout {
+            test: &test_name,
+            cycle_count: elapsed_cycles(cycle_start),
+            timeout_ms,
+        });
+        self.complete_test(TestResult::Timeout, cycle_start);
+
+        self.resume_after_current_test()
+    }
+
+    fn handle_exception(&self, info: &ExceptionInfo) -> ! {
+        let cycle_start = *CURRENT_TEST_CYCLE_START.get().unwrap().read();
+
+        // teardown first, so that a panic in it is reported as the only result for this test
+        run_after_each();
+
+        let Some(current_test) = self.current_test() else {
+            // the exception was raised outside of a test, so it cannot be attributed to one
+            qemu::exit(qemu::ExitCode::HarnessError);
+        };
+
+        // handle according to whether the test was expected to raise this exception
+        let message = match current_test.should_fault() {
+            ShouldFault::Yes => None,
+            ShouldFault::YesWithFault(fault) if fault == info.fault => None,
+            ShouldFault::YesWithFault(fault) => Some(test::panic::format_truncated(format_args!(
+                "CPU exception did not match the expected fault\n      exception: {}\n expected fault: {}",
+                info,
+                fault.name()
+            ))),
+            ShouldFault::No => Some(test::panic::format_truncated(format_args!("{info}"))),
+        };
+
+        match message {
+            None => self.complete_test(TestResult::Success, cycle_start),
+            Some(message) => {
+                write_event(&Event::Exception {
+                    test: &qualified_test_name(current_test),
+                    cycle_count: elapsed_cycles(cycle_start),
+                    message: message.as_str(),
+                    exception: info,
+                });
+                self.complete_test(TestResult::Failure, cycle_start);
             }
         }
 
+        self.resume_after_current_test()
+    }
+}
+
+impl KernelTestRunner {
+    /// Records the current test as failed, writing the failure details to the output.
+    fn fail_current_test(&self, cycle_start: u64, location: &str, message: &str) {
+        let test_name = qualified_test_name(self.current_test().unwrap());
+        let cycle_count = elapsed_cycles(cycle_start);
+        write_event(&Event::Fail {
+            test: &test_name,
+            cycle_count,
+            location,
+            message,
+        });
+        self.complete_test(TestResult::Failure, cycle_start);
+    }
+
+    /// Moves past the current test and continues with the next one (if any) on a clean stack.
+    fn resume_after_current_test(&self) -> ! {
         // increment the test index to move to the next test (if possible)
         let current_index = *CURRENT_TEST_INDEX.get().unwrap().read();
         if !increment_test_index(current_index) {
-            qemu::exit(qemu::ExitCode::Success); // no more tests to run
+            self.after_tests(); // no more tests to run
         }
 
-        // continue with the next test (and all thereafter)
-        self.run_tests(current_index + 1) // continue with next test
+        // continue with the next test (and all thereafter) on a clean stack
+        resume_tests()
     }
 }
 
+/// Called by the watchdog when the current test exceeds its timeout.
+fn timeout_handler() -> ! {
+    TEST_RUNNER.get().unwrap().handle_timeout()
+}
+
+/// Called by the harness exception handlers when the current test raises a CPU exception.
+fn exception_handler(info: &ExceptionInfo) -> ! {
+    TEST_RUNNER.get().unwrap().handle_exception(info)
+}
+
+/// Continues running tests from the current test index, after discarding every stack frame below
+/// the point where runner started. This is used to recover from a panic without nesting a new
+/// runner on top of the panicked test's stack.
+pub fn resume_tests() -> ! {
+    extern "C" fn resume_entry() -> ! {
+        let current_index = *CURRENT_TEST_INDEX.get().unwrap().read();
+        TEST_RUNNER.get().unwrap().run_tests(current_index)
+    }
+
+    let stack_pointer = RUNNER_STACK_POINTER.load(Ordering::SeqCst);
+    if stack_pointer == 0 {
+        // runner has not started, so there is no clean stack to return to
+        qemu::exit(qemu::ExitCode::HarnessError);
+    }
+
+    // safety: runner never returns, so nothing references the frames below its stack pointer
+    unsafe { crate::arch::jump_with_stack(stack_pointer, resume_entry) }
+}
+
+/// Helper function to run the `before_module` fixture for the given module, if configured.
+fn run_before_module(module_path: &'static str) {
+    if let Some(before_module) = args::get_klib_config().and_then(|c| c.before_module) {
+        before_module(module_path);
+    }
+}
+
+/// Helper function to run the `after_module` fixture for the given module, if configured.
+fn run_after_module(module_path: &'static str) {
+    if let Some(after_module) = args::get_klib_config().and_then(|c| c.after_module) {
+        after_module(module_path);
+    }
+}
+
+/// Helper function to run the `before_each` fixture, if configured. Teardown is marked as
+/// pending first, so that `after_each` runs even if `before_each` panics.
+fn run_before_each() {
+    TEARDOWN_PENDING.store(true, Ordering::SeqCst);
+    if let Some(before_each) = args::get_klib_config().and_then(|c| c.before_each) {
+        before_each();
+    }
+}
+
+/// Helper function to run the `after_each` fixture, if configured and still pending for the
+/// current test. It runs at most once per test, even if it panics.
+fn run_after_each() {
+    if !TEARDOWN_PENDING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    if let Some(after_each) = args::get_klib_config().and_then(|c| c.after_each) {
+        after_each();
+    }
+}
+
+/// Helper function to format the qualified name (`modules::name`) of a test.
+fn qualified_test_name(test: &dyn TestCase) -> String<MAX_STRING_LENGTH> {
+    format!("{}::{}", test.modules().unwrap(), test.name()).unwrap()
+}
+
+/// Helper function to get the seed for the shuffled test order, if tests are shuffled.
+fn shuffle_seed() -> Option<u64> {
+    SHUFFLE_SEED.get().copied().flatten()
+}
+
+/// Helper function to determine the shuffle seed from the `kunit.shuffle-seed=<seed>` and
+/// `kunit.shuffle` harness arguments. An explicit seed takes precedence; `kunit.shuffle` alone
+/// picks a seed from the cycle counter.
+fn shuffle_seed_from_args() -> Option<u64> {
+    if let Some(seed) = args::get_test_arg("shuffle-seed") {
+        let Ok(seed) = seed.parse() else {
+            // an unusable seed would silently change the order being reproduced
+            qemu::exit(qemu::ExitCode::HarnessError);
+        };
+        return Some(seed);
+    }
+
+    if args::has_test_arg("shuffle") {
+        return Some(test::shuffle::seed_from_cycle());
+    }
+
+    None
+}
+
+/// Helper function to map a position in the run order (CURRENT_TEST_INDEX) to an index in TESTS.
+fn test_index(position: usize) -> usize {
+    let tests = unsafe { TESTS };
+    match shuffle_seed() {
+        Some(seed) => test::shuffle::shuffled_index(position, tests.len(), seed),
+        None => position,
+    }
+}
+
+/// Helper function to check whether a test is selected by the test filters.
+fn is_selected(test: &dyn TestCase) -> bool {
+    test::filter::is_included(&qualified_test_name(test))
+}
+
+/// Helper function to get the timeout of a test, falling back to the default from `KlibConfig`.
+fn test_timeout_ms(test: &dyn TestCase) -> Option<u64> {
+    test.timeout_ms()
+        .or_else(|| args::get_klib_config().and_then(|config| config.test_timeout_ms))
+}
+
+/// Whether CPU exceptions raised by tests are caught and reported as failures.
+fn catches_exceptions() -> bool {
+    args::get_klib_config().is_some_and(|config| config.catch_exceptions)
+}
+
+/// Whether the given test is expected to raise a CPU exception, in which case the exception
+/// handlers are armed for it regardless of `KlibConfig::catch_exceptions`.
+fn expects_fault(test: &dyn TestCase) -> bool {
+    !matches!(test.should_fault(), ShouldFault::No)
+}
+
 /// Helper function to read the current CPU cycle count using the RDTSC instruction.
 fn read_current_cycle() -> u64 {
     crate::arch::read_cycle()
 }
 
+/// Helper function to compute the cycles elapsed since `cycle_start`. A start of `u64::MAX`
+/// means the start is unknown, in which case zero is returned.
+fn elapsed_cycles(cycle_start: u64) -> u64 {
+    if cycle_start == u64::MAX {
+        return 0;
+    }
+
+    read_current_cycle().saturating_sub(cycle_start)
+}
+
 /// Helper function to assign base + 1 to CURRENT_TEST_INDEX.
 fn increment_test_index(base: usize) -> bool {
     let mut current_test_index = CURRENT_TEST_INDEX.get().unwrap().write();
@@ -0,0 +1,58 @@
+//! Randomized test order, driven by a seed so that any order can be reproduced.
+//!
+//! The order is computed on the fly as a permutation of test indices, so no storage is needed
+//! for the shuffled test list. The permutation is a small Feistel network over the next power of
+//! four, with cycle walking to stay within the number of tests.
+
+/// The number of Feistel rounds. Four rounds are plenty for mixing test order.
+const ROUNDS: u64 = 4;
+
+/// Maps a position in the run order to the index of the test to run at that position. For a
+/// given seed and length, this is a bijection on `0..len`.
+pub fn shuffled_index(position: usize, len: usize, seed: u64) -> usize {
+    if len <= 1 {
+        return position;
+    }
+
+    // the domain is split into two halves of equal bit width
+    let mut half_bits = 1;
+    while 1u64 << (half_bits * 2) < len as u64 {
+        half_bits += 1;
+    }
+
+    // walk the cycle until the permuted value falls within 0..len again
+    let mut value = position as u64;
+    loop {
+        value = feistel(value, half_bits, seed);
+        if value < len as u64 {
+            return value as usize;
+        }
+    }
+}
+
+/// Derives a seed from the cycle counter, for when shuffling is requested without a seed.
+pub fn seed_from_cycle() -> u64 {
+    mix(crate::arch::read_cycle())
+}
+
+fn feistel(value: u64, half_bits: u32, seed: u64) -> u64 {
+    let mask = (1u64 << half_bits) - 1;
+    let mut left = value >> half_bits;
+    let mut right = value & mask;
+
+    for round in 0..ROUNDS {
+        let next_right = left ^ (mix(right ^ seed.rotate_left(round as u32 * 16) ^ round) & mask);
+        left = right;
+        right = next_right;
+    }
+
+    (left << half_bits) | right
+}
+
+/// The SplitMix64 finalizer, used as the Feistel round function.
+fn mix(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
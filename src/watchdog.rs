@@ -0,0 +1,41 @@
+//! The interrupt hardware behind test timeouts on aarch64, for kernels which do not run on the
+//! QEMU `virt` machine.
+//!
+//! The watchdog takes the EL1 virtual timer interrupt through a GICv2 at the QEMU `virt` machine
+//! addresses by default. Another GICv2 can be selected with `KlibConfigBuilder::gic`:
+//!
+//! ```
+//! kunit::klib!("kernel", klib_config = &kunit::KlibConfigBuilder::new_default()
+//!     .test_timeout_ms(5_000)
+//!     .gic(kunit::watchdog::GicV2::new(0x2f00_0000, 0x2c00_0000))
+//!     .build());
+//! ```
+//!
+//! As with the MMIO output sinks, the registers are accessed through their physical addresses,
+//! so the kernel must have them identity mapped while the tests run.
+
+/// A GICv2 interrupt controller, given by the physical addresses of its distributor and CPU
+/// interface.
+///
+/// Only GICv2 (and GICv3 or later in GICv2 compatibility mode, with a memory-mapped CPU
+/// interface) is supported. A GICv3 whose CPU interface is only reachable through system
+/// registers, such as the QEMU `virt` machine with `gic-version=3`, cannot drive the watchdog.
+#[derive(Clone, Copy)]
+pub struct GicV2 {
+    pub(crate) distributor: usize,
+    pub(crate) cpu_interface: usize,
+}
+
+impl GicV2 {
+    /// The GIC of the QEMU `virt` machine (with the default `gic-version=2`).
+    pub const QEMU_VIRT: GicV2 = GicV2::new(0x0800_0000, 0x0801_0000);
+
+    /// Creates a GICv2 with the distributor and the CPU interface at the given physical
+    /// addresses.
+    pub const fn new(distributor: usize, cpu_interface: usize) -> Self {
+        GicV2 {
+            distributor,
+            cpu_interface,
+        }
+    }
+}